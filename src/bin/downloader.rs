@@ -4,28 +4,394 @@ extern crate sha1;
 extern crate url;
 extern crate clap;
 
-use downloader::{mc_data, download};
-use downloader::modloader::fabric;
-use downloader::modloader::fabric::Stability;
-use clap::{Arg, App, value_t};
+use downloader::{gc, instance, launcher, lockfile, modloader, modpack, modrinth, verify};
+use downloader::modloader::Side;
+use downloader::plan::DownloadPlan;
+use clap::{Arg, App, AppSettings, ArgGroup, ArgMatches, SubCommand};
 
+fn main() -> Result<(), Box<dyn std::error::Error>> {
+    //Every loader gets a subcommand for looking through its builds, i.e. downloader fabric list 1.16.2
+    let loader_subcommands = modloader::loaders().iter().map(|loader| {
+        SubCommand::with_name(loader.name())
+                   .about("Look up builds of this modloader")
+                   .setting(AppSettings::SubcommandRequiredElseHelp)
+                   .subcommand(
+                       SubCommand::with_name("list")
+                                  .about("List builds for a version of Minecraft, newest first")
+                                  .arg(
+                                      Arg::with_name("mc_version")
+                                           .help("the version of Minecraft to list builds for.")
+                                           .required(true)
+                                      )
+                                  .arg(
+                                      Arg::with_name("stable")
+                                           .help("Only list stable builds.")
+                                           .long("stable")
+                                           .conflicts_with("unstable")
+                                      )
+                                  .arg(
+                                      Arg::with_name("unstable")
+                                           .help("Only list unstable builds.")
+                                           .long("unstable")
+                                      )
+                                  .arg(
+                                      Arg::with_name("min")
+                                           .help("Oldest version to list.")
+                                           .value_name("version")
+                                           .takes_value(true)
+                                           .long("min")
+                                      )
+                                  .arg(
+                                      Arg::with_name("max")
+                                           .help("Newest version to list.")
+                                           .value_name("version")
+                                           .takes_value(true)
+                                           .long("max")
+                                      )
+                                  .arg(
+                                      Arg::with_name("oldest")
+                                           .help("List oldest first.")
+                                           .long("oldest")
+                                      )
+                   )
+    }).collect::<Vec<App>>();
 
-const VER_MANIFEST: &str = "https://launchermeta.mojang.com/mc/game/version_manifest.json";
+    //Every loader also gets --<name> <build> as a shorthand for --loader <name>@<build>, i.e. -f ""
+    //for the newest stable fabric. The short flag is its first letter, if no loader before it has that.
+    let loader_names: Vec<&'static str> = modloader::loaders().iter().map(|loader| loader.name()).collect();
+    let loader_help: Vec<String> = loader_names.iter().map(|name| {
+        format!("Download a version of {} alongside this minecraft installation. For newest stable, just do '--{} \"\"'", name, name)
+    }).collect();
+    let mut short_flags = Vec::new();
+    let loader_flags = loader_names.iter().zip(&loader_help).map(|(name, help)| {
+        let flag = Arg::with_name(name)
+                        .help(help)
+                        .value_name("build")
+                        .takes_value(true)
+                        .long(name)
+                        .required(false)
+                        .empty_values(true);
+        let short = &name[..1];
+        if short_flags.contains(&short) {
+            flag
+        } else {
+            short_flags.push(short);
+            flag.short(short)
+        }
+    }).collect::<Vec<Arg>>();
+    let mut loader_choices = loader_names.clone();
+    loader_choices.push("loader");
 
-fn main() -> Result<(), Box<dyn std::error::Error>> { 
     let matches = App::new("downloader")
                        .version("0.1")
                        .author("Jackie Edwards <jacksonedwards6@gmail.com>")
                        .about("Downloads Minecraft and its modloaders")
+                       .setting(AppSettings::SubcommandsNegateReqs)
+                       .subcommands(loader_subcommands)
+                       .subcommand(
+                           SubCommand::with_name("instances")
+                                      .about("Manage instances")
+                                      .setting(AppSettings::SubcommandRequiredElseHelp)
+                                      .subcommand(
+                                          SubCommand::with_name("create")
+                                                     .about("Install a new instance")
+                                                     .arg(Arg::with_name("name").help("the new instance's name.").required(true))
+                                                     .arg(Arg::with_name("mc_version").help("the version of Minecraft to install.").required(true))
+                                                     .arg(
+                                                         Arg::with_name("loader")
+                                                              .help("Install a modloader too, as name[@build].")
+                                                              .value_name("name[@build]")
+                                                              .takes_value(true)
+                                                              .short("l")
+                                                              .long("loader")
+                                                         )
+                                                     .arg(
+                                                         Arg::with_name("server")
+                                                              .help("Make it a dedicated server.")
+                                                              .short("s")
+                                                              .long("server")
+                                                         )
+                                                     .arg(
+                                                         Arg::with_name("accept_eula")
+                                                              .help("Accept the Minecraft EULA (https://aka.ms/MinecraftEULA) for a server.")
+                                                              .long("accept-eula")
+                                                              .requires("server")
+                                                         )
+                                                     .arg(
+                                                         Arg::with_name("java")
+                                                              .help("The java executable to run it with.")
+                                                              .value_name("path")
+                                                              .takes_value(true)
+                                                              .long("java")
+                                                         )
+                                                     .arg(
+                                                         Arg::with_name("memory")
+                                                              .help("The most memory java gets, i.e. 4G.")
+                                                              .takes_value(true)
+                                                              .long("memory")
+                                                         )
+                                                     .arg(
+                                                         Arg::with_name("jvm_arg")
+                                                              .help("An extra argument for java. Can be given more than once.")
+                                                              .value_name("arg")
+                                                              .takes_value(true)
+                                                              .long("jvm-arg")
+                                                              .multiple(true)
+                                                              .number_of_values(1)
+                                                              .allow_hyphen_values(true)
+                                                         )
+                                      )
+                                      .subcommand(SubCommand::with_name("list").about("List every instance"))
+                                      .subcommand(
+                                          SubCommand::with_name("rename")
+                                                     .about("Rename an instance")
+                                                     .arg(Arg::with_name("name").required(true))
+                                                     .arg(Arg::with_name("new_name").required(true))
+                                      )
+                                      .subcommand(
+                                          SubCommand::with_name("clone")
+                                                     .about("Copy an instance under a new name")
+                                                     .arg(Arg::with_name("name").required(true))
+                                                     .arg(Arg::with_name("new_name").required(true))
+                                      )
+                                      .subcommand(
+                                          SubCommand::with_name("delete")
+                                                     .about("Delete an instance and everything in it")
+                                                     .arg(Arg::with_name("name").required(true))
+                                                     .arg(
+                                                         Arg::with_name("yes")
+                                                              .help("Delete without asking.")
+                                                              .short("y")
+                                                              .long("yes")
+                                                         )
+                                      )
+                       )
+                       .subcommand(
+                           SubCommand::with_name("import")
+                                      .about("Install a modpack (.mrpack, a CurseForge zip, a packwiz pack, or a Prism/MultiMC instance)")
+                                      .arg(
+                                          Arg::with_name("pack")
+                                               .help("the modpack file to import, a packwiz pack's folder or pack.toml URL, or a Prism instance folder.")
+                                               .required(true)
+                                          )
+                                      .arg(
+                                          Arg::with_name("server")
+                                               .help("Install the pack as a dedicated server.")
+                                               .short("s")
+                                               .long("server")
+                                          )
+                                      .arg(
+                                          Arg::with_name("accept_eula")
+                                               .help("Accept the Minecraft EULA (https://aka.ms/MinecraftEULA) for a server installation.")
+                                               .long("accept-eula")
+                                               .requires("server")
+                                          )
+                                      .arg(
+                                          Arg::with_name("with_optional")
+                                               .help("Also install files the pack marks optional.")
+                                               .long("with-optional")
+                                          )
+                                      .arg(
+                                          Arg::with_name("name")
+                                               .help("The instance to install into. Defaults to one named after the pack.")
+                                               .long("name")
+                                               .takes_value(true)
+                                          )
+                                      .arg(
+                                          Arg::with_name("curseforge_api")
+                                               .help("The CurseForge API to look files up with. Defaults to $CURSEFORGE_API_URL, then the official one.")
+                                               .long("curseforge-api")
+                                               .value_name("url")
+                                               .takes_value(true)
+                                          )
+                       )
+                       .subcommand(
+                           SubCommand::with_name("export")
+                                      .about("Write an instance out as a modpack")
+                                      .arg(
+                                          Arg::with_name("instance")
+                                               .help("the instance to export.")
+                                               .required(true)
+                                          )
+                                      .arg(
+                                          Arg::with_name("format")
+                                               .help("The modpack format to write.")
+                                               .long("format")
+                                               .takes_value(true)
+                                               .possible_values(&["mrpack", "prism"])
+                                               .default_value("mrpack")
+                                          )
+                                      .arg(
+                                          Arg::with_name("output")
+                                               .help("Where to write the pack. Defaults to <name>.<format> here.")
+                                               .short("o")
+                                               .long("output")
+                                               .takes_value(true)
+                                          )
+                                      .arg(
+                                          Arg::with_name("exclude")
+                                               .help("Leave out paths matching this pattern, i.e. 'saves' or 'config/**/*.bak'. Can be given more than once.")
+                                               .long("exclude")
+                                               .takes_value(true)
+                                               .multiple(true)
+                                               .number_of_values(1)
+                                          )
+                                      .arg(
+                                          Arg::with_name("name")
+                                               .help("The pack's name.")
+                                               .long("name")
+                                               .takes_value(true)
+                                          )
+                                      .arg(
+                                          Arg::with_name("pack_version")
+                                               .help("The pack's version.")
+                                               .long("pack-version")
+                                               .takes_value(true)
+                                          )
+                       )
+                       .subcommand(
+                           SubCommand::with_name("add-mod")
+                                      .about("Install the newest compatible version of a mod from Modrinth")
+                                      .arg(
+                                          Arg::with_name("slug")
+                                               .help("the Modrinth project to install, by slug or id.")
+                                               .required(true)
+                                          )
+                                      .arg(
+                                          Arg::with_name("instance")
+                                               .help("the instance to add the mod to.")
+                                               .required(true)
+                                          )
+                                      .arg(
+                                          Arg::with_name("with_optional")
+                                               .help("Also install the mod's optional dependencies.")
+                                               .long("with-optional")
+                                          )
+                                      .arg(
+                                          Arg::with_name("yes")
+                                               .help("Install without asking after showing the plan.")
+                                               .short("y")
+                                               .long("yes")
+                                          )
+                       )
+                       .subcommand(
+                           SubCommand::with_name("install")
+                                      .about("Reinstall an instance from what its manifest says, mods included")
+                                      .arg(
+                                          Arg::with_name("instance")
+                                               .help("the instance to install.")
+                                               .required(true)
+                                          )
+                                      .arg(
+                                          Arg::with_name("locked")
+                                               .help("Install exactly what instance.lock pins, or fail.")
+                                               .long("locked")
+                                          )
+                                      .arg(
+                                          Arg::with_name("accept_eula")
+                                               .help("Accept the Minecraft EULA (https://aka.ms/MinecraftEULA) for a server.")
+                                               .long("accept-eula")
+                                          )
+                                      .arg(
+                                          Arg::with_name("dry_run")
+                                               .help("Print every file the install would download, and how much, without installing anything.")
+                                               .long("dry-run")
+                                               .conflicts_with("locked")
+                                          )
+                                      .arg(
+                                          Arg::with_name("json")
+                                               .help("Print the --dry-run plan as JSON.")
+                                               .long("json")
+                                               .requires("dry_run")
+                                          )
+                       )
+                       .subcommand(
+                           SubCommand::with_name("update")
+                                      .about("Move an instance to the newest loader build and mods, and write a new instance.lock")
+                                      .arg(
+                                          Arg::with_name("instance")
+                                               .help("the instance to update.")
+                                               .required(true)
+                                          )
+                       )
+                       .subcommand(
+                           SubCommand::with_name("verify")
+                                      .about("Check every file an instance needs against its known hash and size, without downloading anything")
+                                      .arg(
+                                          Arg::with_name("instance")
+                                               .help("the instance to check.")
+                                               .required(true)
+                                          )
+                       )
+                       .subcommand(
+                           SubCommand::with_name("repair")
+                                      .about("Download again only the files verify finds broken or missing")
+                                      .arg(
+                                          Arg::with_name("instance")
+                                               .help("the instance to repair.")
+                                               .required(true)
+                                          )
+                       )
+                       .subcommand(
+                           SubCommand::with_name("gc")
+                                      .about("Delete libraries, assets and stored files no instance uses any more")
+                                      .arg(
+                                          Arg::with_name("dry_run")
+                                               .help("Only report what would be deleted.")
+                                               .long("dry-run")
+                                          )
+                       )
                        .arg(
-                           Arg::with_name("fabric")
-                                .help("Download a version of fabric alongside this minecraft installation. For newest stable, just do '-f \"\"'")
-                                .value_name("build")
+                           Arg::with_name("loader")
+                                .help("Install a modloader alongside this minecraft installation, as name[@build]. Without a build, the newest stable one is used.")
+                                .value_name("name[@build]")
                                 .takes_value(true)
-                                .short("f")
-                                .long("fabric")
+                                .short("l")
+                                .long("loader")
                                 .required(false)
+                           )
+                       .args(&loader_flags)
+                       .group(ArgGroup::with_name("loader_choice").args(&loader_choices))
+                       .arg(
+                           Arg::with_name("server")
+                                .help("Install a dedicated server instead of the client.")
+                                .short("s")
+                                .long("server")
+                           )
+                       .arg(
+                           Arg::with_name("accept_eula")
+                                .help("Accept the Minecraft EULA (https://aka.ms/MinecraftEULA) for a server installation.")
+                                .long("accept-eula")
+                                .requires("server")
+                           )
+                       .arg(
+                           Arg::with_name("minecraft_dir")
+                                .help("Install into an existing .minecraft the way the official launcher does, and add a profile for it there. For the default .minecraft, just do '--minecraft-dir \"\"'")
+                                .value_name("path")
+                                .takes_value(true)
+                                .long("minecraft-dir")
                                 .empty_values(true)
+                                .conflicts_with("server")
+                           )
+                       .arg(
+                           Arg::with_name("dry_run")
+                                .help("Print every file the install would download, and how much, without installing anything.")
+                                .long("dry-run")
+                                .conflicts_with("minecraft_dir")
+                           )
+                       .arg(
+                           Arg::with_name("json")
+                                .help("Print the --dry-run plan as JSON.")
+                                .long("json")
+                                .requires("dry_run")
+                           )
+                       .arg(
+                           Arg::with_name("instance")
+                                .help("The instance to install into. Defaults to one named after the version.")
+                                .value_name("name")
+                                .takes_value(true)
+                                .short("i")
+                                .long("instance")
                            )
                        .arg(
                            Arg::with_name("mc_version")
@@ -33,60 +399,364 @@ fn main() -> Result<(), Box<dyn std::error::Error>> {
                                 .required(true)
                            )
                        .get_matches();
-    
+
+    if let Some(import_matches) = matches.subcommand_matches("import") {
+        return import(import_matches);
+    }
+
+    if let Some(instances_matches) = matches.subcommand_matches("instances") {
+        return instances(instances_matches);
+    }
+
+    if let Some(export_matches) = matches.subcommand_matches("export") {
+        return export(export_matches);
+    }
+
+    if let Some(add_matches) = matches.subcommand_matches("add-mod") {
+        return add_mod(add_matches);
+    }
+
+    if let Some(install_matches) = matches.subcommand_matches("install") {
+        return install_instance(install_matches);
+    }
+
+    if let Some(update_matches) = matches.subcommand_matches("update") {
+        return update(update_matches);
+    }
+
+    if let Some(verify_matches) = matches.subcommand_matches("verify") {
+        print!("{}", verify::verify(verify_matches.value_of("instance").unwrap())?);
+        return Ok(());
+    }
+
+    if let Some(repair_matches) = matches.subcommand_matches("repair") {
+        print!("{}", verify::repair(repair_matches.value_of("instance").unwrap())?);
+        return Ok(());
+    }
+
+    if let Some(gc_matches) = matches.subcommand_matches("gc") {
+        print!("{}", gc::collect(gc_matches.is_present("dry_run"))?);
+        return Ok(());
+    }
+
+    if let (name, Some(loader_matches)) = matches.subcommand() {
+        if let Some(loader) = modloader::get_loader(name) {
+            if let Some(list_matches) = loader_matches.subcommand_matches("list") {
+                return list_builds(loader.as_ref(), list_matches);
+            }
+        }
+    }
+
     let mc_version = matches.value_of("mc_version").unwrap();
-    
-
-    let result = Box::new(reqwest::blocking::get(VER_MANIFEST)?
-        .json::<mc_data::MojangVersionManifest>()?);
-
-    
-    let version = result.look_up_version(String::from(mc_version)).unwrap();
-    
-
-    let result = Box::new(reqwest::blocking::get(&version.url[..])?
-        .json::<mc_data::mojang_version_data::MojangVersionData>()?);
-    
-    let instance_path = std::path::Path::new("./installations").join(String::from(mc_version));
-    
-    let mut fabric_version: Option<fabric::FabricBuild> = None;
-
-    if matches.is_present("fabric") {
-        let versions = fabric::get_game_versions(Stability::Stable)?;
-        let fabric_build = versions.iter()
-                                   .find(|ver| ver.version == String::from(mc_version));
-
-
-        fabric_version = if let Some(fabric_build) = fabric_build {
-            let mut builds =fabric::get_fabric_builds_from_version(&fabric_build, Stability::Stable)?;
-            if let Ok(build) = value_t!(matches, "fabric", u32) {
-                let build = builds.into_iter()
-                      .find(|b| b.loader.build == build);
-                if let Some(build) = build {
-                    Some(build)
-                } else {
-                    println!("Fabric build not found.");
-                    return Ok(());
-                }
-            } else {
-                Some(builds.remove(0))
+    let name = matches.value_of("instance").unwrap_or(mc_version);
+
+    //name[@build], where the shorthand flags only carry the build
+    let loader_spec = match matches.value_of("loader") {
+        Some(spec) => Some(String::from(spec)),
+        None => modloader::loaders().iter()
+                                    .map(|loader| loader.name())
+                                    .find(|name| matches.is_present(name))
+                                    .map(|name| format!("{}@{}", name, matches.value_of(name).unwrap_or(""))),
+    };
+
+    if let Some(minecraft_dir) = matches.value_of("minecraft_dir") {
+        return install_to_launcher(minecraft_dir, name, mc_version, loader_spec.as_deref());
+    }
+
+    install(name, mc_version, loader_spec.as_deref(), &matches, None)
+}
+
+fn install_to_launcher(minecraft_dir: &str, name: &str, mc_version: &str, loader_spec: Option<&str>) -> Result<(), Box<dyn std::error::Error>> {
+    let minecraft_dir = match minecraft_dir {
+        "" => launcher::default_minecraft_dir()?,
+        path => std::path::PathBuf::from(path),
+    };
+    let loader_build = match loader_spec {
+        Some(spec) => Some(modloader::resolve_loader(spec, mc_version)?),
+        None => None,
+    };
+    let version_id = launcher::install(&minecraft_dir, name, mc_version, loader_build.as_ref())?;
+    println!("Installed {} to {} as the launcher profile {}", version_id, minecraft_dir.display(), name);
+    Ok(())
+}
+
+fn install(name: &str, mc_version: &str, loader_spec: Option<&str>, matches: &ArgMatches, java: Option<instance::JavaSettings>) -> Result<(), Box<dyn std::error::Error>> {
+    let loader_build = match loader_spec {
+        Some(spec) => match modloader::resolve_loader(spec, mc_version) {
+            Ok(loader_build) => Some(loader_build),
+            Err(e) => {
+                println!("{}", e);
+                return Ok(());
             }
+        },
+        None => None,
+    };
+
+    let side = if matches.is_present("server") { Side::Server } else { Side::Client };
+    if matches.is_present("dry_run") {
+        return print_plan(&instance::plan(name, mc_version, loader_build.as_ref(), side)?, matches);
+    }
+    let accept_eula = matches.is_present("accept_eula");
+    let (directory, manifest) = instance::install(name, mc_version, loader_build.as_ref(), side, accept_eula, java)?;
+    println!("Installed {} to {}", manifest.name, directory.display());
+
+    if side == Side::Server && !accept_eula {
+        println!("Server installed. Run again with --accept-eula or edit eula.txt to agree to the Minecraft EULA.");
+    }
+    Ok(())
+}
+
+fn install_instance(matches: &ArgMatches) -> Result<(), Box<dyn std::error::Error>> {
+    let name = matches.value_of("instance").unwrap();
+    let accept_eula = matches.is_present("accept_eula");
+    if matches.is_present("locked") {
+        let directory = lockfile::install_locked(name, accept_eula)?;
+        println!("Installed {} to {} from its lockfile", name, directory.display());
+        return Ok(());
+    }
+
+    //The manifest already has the exact loader build, so this doesn't move anything forward
+    let (directory, manifest) = instance::find(name)?;
+    let loader_build = match &manifest.loader {
+        Some(loader) => Some(modloader::resolve_loader(&format!("{}@{}", loader.name, loader.version), &manifest.minecraft)?),
+        None => None,
+    };
+    //Mods aren't part of installing minecraft, so they're put back from the manifest afterwards.
+    //Pack files without a download can only come from the pack again.
+    let mods: Vec<_> = verify::mod_files(&directory, &manifest)?.into_iter()
+                                                              .filter(|file| file.url.is_some())
+                                                              .collect();
+    if matches.is_present("dry_run") {
+        let mut plan = instance::plan(name, &manifest.minecraft, loader_build.as_ref(), manifest.side)?;
+        plan.extend(mods);
+        return print_plan(&plan, matches);
+    }
+    let (directory, _) = instance::install(name, &manifest.minecraft, loader_build.as_ref(), manifest.side, accept_eula, None)?;
+    DownloadPlan::new(&manifest.minecraft, &directory, mods).execute(&reqwest::blocking::Client::new())?;
+    println!("Installed {} to {}", name, directory.display());
+    Ok(())
+}
+
+fn print_plan(plan: &DownloadPlan, matches: &ArgMatches) -> Result<(), Box<dyn std::error::Error>> {
+    if matches.is_present("json") {
+        println!("{}", serde_json::to_string_pretty(plan)?);
+    } else {
+        print!("{}", plan);
+    }
+    Ok(())
+}
+
+fn update(matches: &ArgMatches) -> Result<(), Box<dyn std::error::Error>> {
+    let (directory, differences) = lockfile::update(matches.value_of("instance").unwrap())?;
+    if differences.is_empty() {
+        println!("{} is already up to date", directory.display());
+    }
+    for difference in differences {
+        println!("  {}", difference);
+    }
+    Ok(())
+}
+
+fn instances(matches: &ArgMatches) -> Result<(), Box<dyn std::error::Error>> {
+    match matches.subcommand() {
+        ("create", Some(matches)) => {
+            let name = matches.value_of("name").unwrap();
+            if instance::exists(name) {
+                println!("There's already an instance called {}", name);
+                return Ok(());
+            }
+            let java = instance::JavaSettings {
+                path: matches.value_of("java").map(String::from),
+                memory: matches.value_of("memory").map(String::from),
+                args: matches.values_of("jvm_arg").map(|v| v.map(String::from).collect()).unwrap_or_default(),
+            };
+            install(name, matches.value_of("mc_version").unwrap(), matches.value_of("loader"), matches, Some(java))?;
+        }
+        ("list", Some(_)) => {
+            let instances = instance::list()?;
+            if instances.is_empty() {
+                println!("No instances yet");
+            }
+            for (_, manifest) in instances {
+                let loader = manifest.loader.as_ref()
+                                            .map(|l| format!(" {} {}", l.name, l.version))
+                                            .unwrap_or_default();
+                println!("{} ({}): minecraft {}{}, {} mods, updated {}",
+                         manifest.name, manifest.side.name(), manifest.minecraft, loader, manifest.mods.len(), manifest.updated);
+            }
+        }
+        ("rename", Some(matches)) => {
+            let directory = instance::rename(matches.value_of("name").unwrap(), matches.value_of("new_name").unwrap())?;
+            println!("Moved to {}", directory.display());
+        }
+        ("clone", Some(matches)) => {
+            let directory = instance::clone(matches.value_of("name").unwrap(), matches.value_of("new_name").unwrap())?;
+            println!("Copied to {}", directory.display());
+        }
+        ("delete", Some(matches)) => {
+            let name = matches.value_of("name").unwrap();
+            let (directory, _) = instance::find(name)?;
+            if !matches.is_present("yes") && !confirm(&format!("Delete {} and everything in it?", directory.display()))? {
+                return Ok(());
+            }
+            instance::delete(name)?;
+            println!("Deleted {}", name);
+        }
+        _ => (),
+    }
+    Ok(())
+}
+
+fn list_builds(loader: &dyn modloader::ModLoader, matches: &ArgMatches) -> Result<(), Box<dyn std::error::Error>> {
+    let mc_version = matches.value_of("mc_version").unwrap();
+    let query = modloader::VersionQuery {
+        stability: if matches.is_present("stable") {
+            modloader::Stability::Stable
+        } else if matches.is_present("unstable") {
+            modloader::Stability::Unstable
+        } else {
+            modloader::Stability::Both
+        },
+        min_version: matches.value_of("min").map(String::from),
+        max_version: matches.value_of("max").map(String::from),
+        version: None,
+        selection: if matches.is_present("oldest") {
+            modloader::Selection::Oldest
         } else {
-            println!("No fabric builds found for {}", mc_version);
-            return Ok(());
-        };
+            modloader::Selection::Newest
+        },
+    };
+
+    let builds = loader.builds(mc_version, &query)?;
+    if builds.is_empty() {
+        println!("No {} builds found for {}", loader.name(), mc_version);
+    }
+    for build in builds {
+        let number = build.build.map(|b| format!(" (build {})", b)).unwrap_or_default();
+        let stability = if build.stable { "stable" } else { "unstable" };
+        println!("{}{} {}", build.version, number, stability);
     }
+    Ok(())
+}
 
+fn import(matches: &ArgMatches) -> Result<(), Box<dyn std::error::Error>> {
+    let location = matches.value_of("pack").unwrap();
+    let pack = std::path::Path::new(location);
+    let options = modpack::ImportOptions {
+        side: if matches.is_present("server") { Side::Server } else { Side::Client },
+        include_optional: matches.is_present("with_optional"),
+        accept_eula: matches.is_present("accept_eula"),
+        name: matches.value_of("name").map(String::from),
+    };
 
-    println!("downloading from {}", &version.url);
-    download::install_to_directory(&result, &instance_path)?;
+    //packwiz packs are folders or URLs rather than a single file
+    if modpack::packwiz::is_packwiz(location) {
+        let (directory, manual) = modpack::packwiz::import_packwiz(location, &options)?;
+        println!("Installed to {}", directory.display());
+        print_manual_downloads(&manual);
+        return Ok(());
+    }
 
-    if let Some(fabric_version) = fabric_version {
-        println!("{:?}", fabric_version);
-        fabric::install_fabric_at_instance(fabric_version, &instance_path.join(&version.id))?;
+    if modpack::prism::is_prism(pack) {
+        let directory = modpack::prism::import_prism(pack, &options)?;
+        println!("Installed to {}", directory.display());
+        return Ok(());
     }
 
+    match modpack::detect_format(pack)? {
+        Some(modpack::PackFormat::Mrpack) => {
+            let directory = modpack::mrpack::import_mrpack(pack, &options)?;
+            println!("Installed to {}", directory.display());
+        }
+        Some(modpack::PackFormat::CurseForge) => {
+            let mut api = modpack::curseforge::CurseForgeApi::from_env();
+            if let Some(url) = matches.value_of("curseforge_api") {
+                api.base_url = String::from(url);
+            }
+            let (directory, manual) = modpack::curseforge::import_curseforge(pack, &options, &api)?;
+            println!("Installed to {}", directory.display());
+            print_manual_downloads(&manual);
+        }
+        Some(modpack::PackFormat::Prism) => {
+            let directory = modpack::prism::import_prism(pack, &options)?;
+            println!("Installed to {}", directory.display());
+        }
+        None => println!("{} isn't a supported modpack format", pack.display()),
+    }
+    Ok(())
+}
+
+fn print_manual_downloads(manual: &[modpack::curseforge::ManualDownload]) {
+    if manual.is_empty() {
+        return;
+    }
+    println!("These files can't be downloaded outside of CurseForge. Download them by hand, then put them where they go:");
+    for download in manual {
+        println!("  {} ({}): {} -> {}", download.name, download.file_name, download.url, download.path.display());
+    }
+}
 
+fn export(matches: &ArgMatches) -> Result<(), Box<dyn std::error::Error>> {
+    let name = matches.value_of("instance").unwrap();
+    let (directory, _) = instance::find(name)?;
+    let options = modpack::mrpack::ExportOptions {
+        name: matches.value_of("name").map(String::from),
+        version: matches.value_of("pack_version").map(String::from),
+        summary: None,
+        exclude: matches.values_of("exclude").map(|v| v.map(String::from).collect()).unwrap_or_default(),
+    };
 
+    let format = matches.value_of("format").unwrap();
+    //Prism imports plain zips
+    let extension = if format == "prism" { "zip" } else { format };
+    let output = match matches.value_of("output") {
+        Some(output) => std::path::PathBuf::from(output),
+        None => std::path::PathBuf::from(format!("{}.{}", options.name.as_deref().unwrap_or(name), extension)),
+    };
+    if format == "prism" {
+        modpack::prism::export_prism(&directory, &output, &options)?;
+        println!("Exported {} to {}", name, output.display());
+        return Ok(());
+    }
+    let index = modpack::mrpack::export_mrpack(&directory, &output, &options)?;
+    println!("Exported {} {} to {} with {} downloads", index.name, index.version_id, output.display(), index.files.len());
+    Ok(())
+}
+
+fn add_mod(matches: &ArgMatches) -> Result<(), Box<dyn std::error::Error>> {
+    let slug = matches.value_of("slug").unwrap();
+    let (directory, mut manifest) = instance::find(matches.value_of("instance").unwrap())?;
+    let client = modrinth::client()?;
+    let options = modrinth::ResolveOptions { include_optional: matches.is_present("with_optional") };
+    let plan = modrinth::resolver::resolve(&client, slug, &manifest, options)?;
+
+    println!("Adding {} to {}:", slug, directory.display());
+    print!("{}", plan);
+    if plan.has_conflicts() {
+        println!("Nothing was installed.");
+        return Ok(());
+    }
+    if plan.to_install().next().is_none() {
+        println!("Everything is already installed.");
+        return Ok(());
+    }
+    if !matches.is_present("yes") && !confirm("Install these mods?")? {
+        return Ok(());
+    }
+
+    for planned in plan.to_install() {
+        modrinth::install_version(&client, &planned.project, &planned.version, &directory, &mut manifest)?;
+    }
+    manifest.save(&directory)?;
+    lockfile::update_mods(&directory, &manifest)?;
     Ok(())
 }
+
+fn confirm(question: &str) -> Result<bool, Box<dyn std::error::Error>> {
+    use std::io::Write;
+    print!("{} [y/N] ", question);
+    std::io::stdout().flush()?;
+    let mut answer = String::new();
+    std::io::stdin().read_line(&mut answer)?;
+    Ok(matches!(answer.trim(), "y" | "Y" | "yes"))
+}
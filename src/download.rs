@@ -1,10 +1,13 @@
 use super::mc_data::mojang_version_data::{Artifact, MojangVersionData, Os};
+use super::modloader::Side;
+use super::plan::{self, DownloadPlan};
+use super::store;
 use serde::{Deserialize, Serialize};
+use std::collections::HashMap;
 use std::path::Path;
 use phf::phf_map;
-use rayon::prelude::*;
 
-const RESOURCE_URL: &str = "https://resources.download.minecraft.net";
+pub const RESOURCE_URL: &str = "https://resources.download.minecraft.net";
 static OS_MAP: phf::Map<&'static str, &'static str> = phf_map! {
     "macos" => "osx",
     "linux" => "linux",
@@ -18,97 +21,83 @@ pub enum InstallError {
     IOError(std::io::Error),
     HashError(String),
     JSONError(serde_json::error::Error),
+    ZipError(zip::result::ZipError),
+    TOMLError(toml::de::Error),
+    Unsupported(String),
+}
+
+#[derive(Serialize, Debug, Clone, Copy, PartialEq)]
+#[serde(rename_all = "snake_case")]
+pub enum FileKind {
+    AssetIndex,
+    AssetObject,
+    Library,
+    Native,
+    GameJar,
+    LoggingConfig,
+    LoaderLibrary,
+    Mod,
+}
+
+impl FileKind {
+    pub fn name(&self) -> &'static str {
+        match self {
+            FileKind::AssetIndex => "asset index",
+            FileKind::AssetObject => "asset object",
+            FileKind::Library => "library",
+            FileKind::Native => "native",
+            FileKind::GameJar => "game jar",
+            FileKind::LoggingConfig => "logging config",
+            FileKind::LoaderLibrary => "loader library",
+            FileKind::Mod => "mod",
+        }
+    }
+
+    //Assets are already shared by hash, everything else that's a jar goes through the store
+    pub fn stored(&self) -> bool {
+        !matches!(self, FileKind::AssetIndex | FileKind::AssetObject | FileKind::LoggingConfig)
+    }
+}
+
+//Only the part of an asset index anything here needs
+#[derive(Deserialize)]
+pub struct AssetIndexObjects {
+    pub objects: HashMap<String, AssetObject>,
+}
+
+#[derive(Deserialize)]
+pub struct AssetObject {
+    pub hash: String,
+    pub size: u64,
+}
+
+pub fn human_bytes(bytes: u64) -> String {
+    let units = ["B", "KiB", "MiB", "GiB", "TiB"];
+    let mut size = bytes as f64;
+    let mut unit = 0;
+    while size >= 1024.0 && unit < units.len() - 1 {
+        size /= 1024.0;
+        unit += 1;
+    }
+    if unit == 0 { format!("{} B", bytes) } else { format!("{:.1} {}", size, units[unit]) }
 }
 
 pub fn install_to_directory(
     version: &MojangVersionData,
     directory: &Path,
 ) -> Result<(), InstallError> {
-    let resource_url = url::Url::parse(RESOURCE_URL)?;
-    //Need a builder for temporary files so that we don't leave half an installation
-    //let tmp_dir = tempfile::Builder::new().prefix("modpacker").tempdir()?;
-    ///////////////First step: make sure assets folders exist
-    let assets_path = Path::new("./assets");
-    std::fs::create_dir_all(&assets_path.join("indexes"))?;
-    std::fs::create_dir_all(&assets_path.join("objects"))?;
-    ///////////////
-    std::fs::create_dir_all(&directory)?;
-    let client = reqwest::blocking::Client::new();
+    let plan = plan::plan_install(version, directory, Side::Client)?;
+    execute_install(&plan, version)
+}
 
-    let assets_filename = String::from(format!("{}{}", &version.assets, ".json"));
-
-    //if asset index doesn't exist, create and populate it. Otherwise, carry on
-    if let Err(_) = std::fs::read(
-        Path::new("./assets/indexes/")
-            .join(&version.assets)
-            .with_extension("json"),
-    ) {
-        try_download_and_write(
-            &version.asset_index.url,
-            &*assets_path.join("indexes"),
-            &assets_filename,
-            Some(&client),
-        )
-        ?;
-    }
+//Downloads what the plan says is missing, then saves the version json next to it
+pub fn execute_install(plan: &DownloadPlan, version: &MojangVersionData) -> Result<(), InstallError> {
+    std::fs::create_dir_all(&plan.directory)?;
+    let client = reqwest::blocking::Client::new();
+    plan.execute(&client)?;
 
-    //if the objects folder isn't properly populated, create it
-        let assets = std::fs::read_to_string(format!("./assets/indexes/{}", assets_filename))?;
-        let objects: ResourceObjectData =
-            serde_json::from_str::<ResourceData>(&assets[..])?.objects;
-        objects.extra.par_iter().for_each(|(name, hash_data)| {
-            //Files are stored in folders named with the first two characters in a hash.
-            let dir = &assets_path.join("objects").join(&hash_data.hash[..2]);
-            std::fs::create_dir_all(dir).unwrap();
-
-            //Does the file exist? If so, is the hash correct?
-            let download_necessary = match std::fs::read(&dir.join(&hash_data.hash[..])) {
-                Err(_) => true,
-                Ok(f) => sha1::Sha1::from(f).digest().to_string() != &hash_data.hash[..],
-            };
-            if download_necessary {
-                try_download_and_write(
-                    &resource_url
-                        .join(&format!("{}/{}", &hash_data.hash[..2], &hash_data.hash[..])[..]).unwrap()
-                        .to_string(),
-                    &dir,
-                    &hash_data.hash,
-                    Some(&client),
-                ).unwrap();
-
-                println!("downloaded {}", name);
-            }
-        });
-    //////
-    //Next phase: installing libraries
-    let lib_path = Path::new("./libraries");
-    std::fs::create_dir_all(&lib_path)?;
-
-    let (mut lib_artifacts, mut nat_artifacts) = get_needed_libraries(&version);
-    lib_artifacts.append(&mut nat_artifacts);
-
-    lib_artifacts.par_iter().for_each(|lib| {
-        let path = lib.path.clone().unwrap();
-        let mut path = lib_path.join(path);
-        let name = path.file_name()
-                        .unwrap_or(std::ffi::OsStr::new("THIS_IS_BAD_TELL_THE_DEV"))
-                        .to_str()
-                        .unwrap();
-        let name = String::from(name);
-        path.pop();
-        try_download_and_write(&lib.url,
-                               &path, 
-                               &name,
-                               Some(&client)).unwrap();
-    });
-
-    try_download_and_write(&version.downloads.client.url, 
-                           &directory, 
-                           &String::from("client.jar"),
-                           Some(&client))?;
-    
     let file = serde_json::to_string_pretty(version)?;
-    let file_path = directory.join("version_info.json");
+    let file_path = plan.directory.join("version_info.json");
     let should_save_version = match std::fs::read_to_string(&file_path) {
         Err(_) => true,
         Ok(s) => s != file
@@ -118,39 +107,27 @@ pub fn install_to_directory(
         std::fs::write(file_path, file)?;
     }
 
-    //Download logger data
-    let file_path = directory.join("client.xml");
-    let should_get_logger = match std::fs::read(&file_path) {
-        Err(_) => true,
-        Ok(b) => sha1::Sha1::from(b).digest().to_string() != version.logging.client.file.sha1 
-    };
-    
-    if should_get_logger {
-        let logging_client = &version.logging.client;
-        try_download_and_write(&logging_client.file.url,
-                               &directory,
-                               &String::from("client.xml"),
-                               Some(&client))?;
-    }
-
     Ok(())
 }
 
-#[derive(Serialize, Deserialize)]
-struct ResourceData {
-    objects: ResourceObjectData,
-}
-
-#[derive(Serialize, Deserialize)]
-struct ResourceObjectData {
-    #[serde(flatten)]
-    extra: std::collections::HashMap<String, HashData>,
+//Fills in the shared asset store for a version: its index, and every object the index lists
+pub fn install_assets(
+    version: &MojangVersionData,
+    assets_path: &Path,
+    client: &reqwest::blocking::Client,
+) -> Result<(), InstallError> {
+    let files = plan::plan_assets(version, assets_path, true)?;
+    DownloadPlan::new(&version.id, assets_path, files).execute(client)
 }
 
-#[derive(Serialize, Deserialize)]
-struct HashData {
-    hash: String,
-    size: u32,
+//Downloads a version's libraries and natives into a library store laid out like a maven repo
+pub fn install_libraries(
+    version: &MojangVersionData,
+    lib_path: &Path,
+    client: &reqwest::blocking::Client,
+) -> Result<(), InstallError> {
+    let files = plan::plan_libraries(version, lib_path);
+    DownloadPlan::new(&version.id, lib_path, files).execute(client)
 }
 
 pub fn get_needed_libraries(version: &MojangVersionData) -> (Vec<Artifact>, Vec<Artifact>){
@@ -201,8 +178,14 @@ pub fn get_needed_libraries(version: &MojangVersionData) -> (Vec<Artifact>, Vec<
 
         //if there's an artifact, add it to the list and check for natives
         if should_download_artifact {
+            //Not every version json has paths, but the name says where it goes
+            let coordinate = lib.coordinate().ok();
             if let Some(library) = &lib.downloads.artifact {
-                libs.push(library.clone());
+                let mut library = library.clone();
+                if library.path.is_none() {
+                    library.path = coordinate.as_ref().map(|c| c.path());
+                }
+                libs.push(library);
             }
         
             //if there are any platform-specific artifacts (classifiers,) download those as well
@@ -215,7 +198,13 @@ pub fn get_needed_libraries(version: &MojangVersionData) -> (Vec<Artifact>, Vec<
                 };
 
                 if let Some(classifier) = classifier {
-                    nats.push(classifier.clone());
+                    let mut native = classifier.clone();
+                    if native.path.is_none() {
+                        native.path = coordinate.as_ref()
+                                                .zip(lib.natives_classifier(&os_name))
+                                                .map(|(c, natives)| c.with_classifier(&natives).path());
+                    }
+                    nats.push(native);
                 }
             }
         
@@ -261,16 +250,36 @@ pub fn try_download_and_write(
     Ok(())
 }
 
-fn download_and_check(
+//Like try_download_and_write, but skips the download if the file on disk already matches the
+//hash, and refuses to write anything that doesn't. The file ends up linked into the store.
+pub fn try_download_verified(
+    url: &String,
+    sha1: &str,
+    dir: &Path,
+    name: &String,
+    client: Option<&reqwest::blocking::Client>,
+) -> Result<(), InstallError> {
+    let destination = dir.join(name);
+    if let Ok(bytes) = std::fs::read(&destination) {
+        if sha1::Sha1::from(&bytes).digest().to_string() == sha1 {
+            //Files from before the store get moved into it
+            store::place(&bytes, &destination)?;
+            return Ok(());
+        }
+    }
+    store::fetch(url, sha1, &destination, client)
+}
+
+pub fn download_and_check(
     url: &String,
     client: Option<&reqwest::blocking::Client>,
 ) -> Result<Vec<u8>, InstallError> {
     let result = match client {
-        None => reqwest::blocking::get(&url[..])?.bytes(),
-        Some(client) => client.get(url).send()?.bytes(),
+        None => reqwest::blocking::get(&url[..])?.error_for_status()?.bytes(),
+        Some(client) => client.get(url).send()?.error_for_status()?.bytes(),
     }?;
 
-    Ok(result.iter().map(|v| -> u8 { *v }).collect())
+    Ok(result.to_vec())
 }
 
 impl From<std::io::Error> for InstallError {
@@ -290,6 +299,16 @@ impl From<serde_json::Error> for InstallError {
         Self::JSONError(error)
     }
 }
+impl From<zip::result::ZipError> for InstallError {
+    fn from(error: zip::result::ZipError) -> Self {
+        Self::ZipError(error)
+    }
+}
+impl From<toml::de::Error> for InstallError {
+    fn from(error: toml::de::Error) -> Self {
+        Self::TOMLError(error)
+    }
+}
 impl From<url::ParseError> for InstallError {
     fn from(error: url::ParseError) -> Self {
         Self::URLError(error)
@@ -306,6 +325,9 @@ impl fmt::Display for InstallError {
             Self::JSONError(e) => e.fmt(f)?,
             Self::IOError(e) => e.fmt(f)?,
             Self::HashError(e) => e.fmt(f)?,
+            Self::ZipError(e) => e.fmt(f)?,
+            Self::TOMLError(e) => e.fmt(f)?,
+            Self::Unsupported(e) => e.fmt(f)?,
         }
         write!(f, "")
     }
@@ -319,7 +341,10 @@ impl Error for InstallError {
             Self::URLError(e) => e.source(),
             Self::JSONError(e) => e.source(),
             Self::IOError(e) => e.source(),
-            Self::HashError(e) => None,
+            Self::HashError(_) => None,
+            Self::ZipError(e) => e.source(),
+            Self::TOMLError(e) => e.source(),
+            Self::Unsupported(_) => None,
         }
     }
 }
@@ -0,0 +1,209 @@
+use std::collections::HashSet;
+use std::fmt;
+use std::path::{Path, PathBuf};
+use super::download::{self, AssetIndexObjects, InstallError, human_bytes};
+use super::instance::{INSTALLATIONS_DIR, SERVERS_DIR, VERSION_FILE};
+use super::lockfile;
+use super::mc_data::mojang_version_data::MojangVersionData;
+use super::modloader::PROFILE_FILE;
+use super::modpack::walk_files;
+use super::store::STORE_DIR;
+
+const LIBRARIES_DIR: &str = "./libraries";
+const ASSETS_DIR: &str = "./assets";
+
+//What was (or would be) deleted from one of the shared folders
+#[derive(Debug, Default)]
+pub struct Sweep {
+    pub files: Vec<PathBuf>,
+    pub bytes: u64,
+}
+
+#[derive(Debug, Default)]
+pub struct GcReport {
+    pub dry_run: bool,
+    pub instances: usize,
+    pub libraries: Sweep,
+    pub asset_indexes: Sweep,
+    pub asset_objects: Sweep,
+    pub blobs: Sweep,
+}
+
+impl GcReport {
+    pub fn bytes(&self) -> u64 {
+        self.libraries.bytes + self.asset_indexes.bytes + self.asset_objects.bytes + self.blobs.bytes
+    }
+}
+
+impl fmt::Display for GcReport {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        let verb = if self.dry_run { "Would delete" } else { "Deleted" };
+        writeln!(f, "Kept everything used by {} instances.", self.instances)?;
+        for (what, sweep) in &[("libraries", &self.libraries),
+                               ("asset indexes", &self.asset_indexes),
+                               ("asset objects", &self.asset_objects),
+                               ("store blobs", &self.blobs)] {
+            writeln!(f, "{} {} {} ({})", verb, sweep.files.len(), what, human_bytes(sweep.bytes))?;
+        }
+        writeln!(f, "{} {} in total", if self.dry_run { "Would reclaim" } else { "Reclaimed" }, human_bytes(self.bytes()))
+    }
+}
+
+//What every installed instance still uses out of the shared folders
+#[derive(Default)]
+struct Reachable {
+    instances: Vec<PathBuf>,
+    libraries: HashSet<String>,
+    //Folders, relative to ./libraries, where everything is kept
+    library_folders: HashSet<String>,
+    asset_indexes: HashSet<String>,
+    asset_objects: HashSet<String>,
+}
+
+//Deletes whatever in ./libraries, ./assets and ./store no installed instance uses any more. With
+//dry_run nothing is deleted, only counted.
+pub fn collect(dry_run: bool) -> Result<GcReport, InstallError> {
+    let reachable = reachable()?;
+    let mut report = GcReport { dry_run, instances: reachable.instances.len(), ..GcReport::default() };
+
+    report.libraries = sweep(Path::new(LIBRARIES_DIR), dry_run, |path| {
+        reachable.libraries.contains(path)
+            || reachable.library_folders.iter().any(|folder| path.starts_with(&format!("{}/", folder)))
+    })?;
+    report.asset_indexes = sweep(&Path::new(ASSETS_DIR).join("indexes"), dry_run, |path| {
+        reachable.asset_indexes.contains(path.trim_end_matches(".json"))
+    })?;
+    report.asset_objects = sweep(&Path::new(ASSETS_DIR).join("objects"), dry_run, |path| {
+        path.rsplit('/').next().map(|hash| reachable.asset_objects.contains(hash)).unwrap_or(false)
+    })?;
+
+    //Every file in an instance is a possible link to a blob, but only ones the size of some blob
+    //are worth hashing
+    let blob_sizes = blob_sizes()?;
+    let mut used_blobs = HashSet::new();
+    let candidates = reachable.instances.iter()
+                                        .map(|dir| (dir.clone(), walk_files(dir)))
+                                        .chain(std::iter::once((PathBuf::from(LIBRARIES_DIR), Ok(reachable.libraries.iter().cloned().collect()))));
+    for (dir, files) in candidates {
+        for file in files? {
+            let path = dir.join(&file);
+            let size = match std::fs::metadata(&path) {
+                Ok(metadata) => metadata.len(),
+                Err(_) => continue,
+            };
+            if blob_sizes.contains(&size) {
+                used_blobs.insert(sha1::Sha1::from(std::fs::read(&path)?).digest().to_string());
+            }
+        }
+    }
+    report.blobs = sweep(Path::new(STORE_DIR), dry_run, |path| {
+        path.rsplit('/').next().map(|sha1| used_blobs.contains(sha1)).unwrap_or(false)
+    })?;
+
+    Ok(report)
+}
+
+//Goes by what's in each instance folder rather than instance.json, so folders installed before
+//there were manifests keep their libraries too
+fn reachable() -> Result<Reachable, InstallError> {
+    let mut reachable = Reachable::default();
+    for root in &[INSTALLATIONS_DIR, SERVERS_DIR] {
+        let entries = match std::fs::read_dir(root) {
+            Ok(entries) => entries,
+            Err(e) if e.kind() == std::io::ErrorKind::NotFound => continue,
+            Err(e) => return Err(e.into()),
+        };
+        for entry in entries {
+            let directory = entry?.path();
+            if directory.is_dir() {
+                add_instance(&directory, &mut reachable)?;
+            }
+        }
+    }
+    Ok(reachable)
+}
+
+fn add_instance(directory: &Path, reachable: &mut Reachable) -> Result<(), InstallError> {
+    //Without its version there's no telling what an instance needs, so nothing gets deleted
+    let version_file = directory.join(VERSION_FILE);
+    if !version_file.is_file() {
+        return Err(InstallError::Unsupported(format!("{} has no {}, so gc can't tell what it uses", directory.display(), VERSION_FILE)));
+    }
+    let vanilla: MojangVersionData = serde_json::from_slice(&std::fs::read(&version_file)?)?;
+
+    let (libs, natives) = download::get_needed_libraries(&vanilla);
+    reachable.libraries.extend(libs.into_iter().chain(natives).filter_map(|artifact| artifact.path));
+
+    if directory.join(PROFILE_FILE).is_file() {
+        for (path, _) in lockfile::profile_libraries(directory)? {
+            //Forge's installer puts what its processors made next to the jars its profile names
+            if let Some((folder, _)) = path.rsplit_once('/') {
+                reachable.library_folders.insert(String::from(folder));
+            }
+            reachable.libraries.insert(path);
+        }
+        //Nothing names the patched game jars at all, forge finds them by the minecraft version
+        reachable.library_folders.insert(format!("net/minecraft/client/{}", vanilla.id));
+        if let Ok(entries) = std::fs::read_dir(Path::new(LIBRARIES_DIR).join("net/minecraft/client")) {
+            for entry in entries {
+                let name = entry?.file_name().to_string_lossy().into_owned();
+                if name.starts_with(&format!("{}-", vanilla.id)) {
+                    reachable.library_folders.insert(format!("net/minecraft/client/{}", name));
+                }
+            }
+        }
+    }
+
+    let index_path = Path::new(ASSETS_DIR).join("indexes").join(format!("{}.json", vanilla.assets));
+    reachable.asset_indexes.insert(vanilla.assets.clone());
+    if let Ok(bytes) = std::fs::read(&index_path) {
+        let index: AssetIndexObjects = serde_json::from_slice(&bytes)?;
+        reachable.asset_objects.extend(index.objects.into_values().map(|object| object.hash));
+    }
+
+    reachable.instances.push(directory.to_path_buf());
+    Ok(())
+}
+
+fn blob_sizes() -> Result<HashSet<u64>, InstallError> {
+    let mut sizes = HashSet::new();
+    if !Path::new(STORE_DIR).is_dir() {
+        return Ok(sizes);
+    }
+    for file in walk_files(Path::new(STORE_DIR))? {
+        sizes.insert(std::fs::metadata(Path::new(STORE_DIR).join(file))?.len());
+    }
+    Ok(sizes)
+}
+
+//Deletes every file under root that keep says no to, then any folders that leaves empty
+fn sweep<F: Fn(&str) -> bool>(root: &Path, dry_run: bool, keep: F) -> Result<Sweep, InstallError> {
+    let mut swept = Sweep::default();
+    if !root.is_dir() {
+        return Ok(swept);
+    }
+    for file in walk_files(root)? {
+        if keep(&file) {
+            continue;
+        }
+        let path = root.join(&file);
+        swept.bytes += std::fs::metadata(&path)?.len();
+        if !dry_run {
+            std::fs::remove_file(&path)?;
+            remove_empty_parents(root, &path);
+        }
+        swept.files.push(path);
+    }
+    Ok(swept)
+}
+
+fn remove_empty_parents(root: &Path, path: &Path) {
+    let mut folder = path.parent();
+    while let Some(dir) = folder {
+        //remove_dir only removes empty folders, so the first one that isn't stops it
+        if dir == root || std::fs::remove_dir(dir).is_err() {
+            break;
+        }
+        folder = dir.parent();
+    }
+}
@@ -0,0 +1,445 @@
+use std::collections::BTreeMap;
+use std::path::{Path, PathBuf};
+use serde::{Serialize, Deserialize};
+use super::download::{self, InstallError};
+use super::lockfile::{LockedFile, Lockfile};
+use super::mc_data::MojangVersionManifest;
+use super::mc_data::mojang_version_data::MojangVersionData;
+use super::modloader::{ModLoader, LoaderBuild, Side};
+use super::plan::{self, DownloadPlan};
+use super::server;
+use super::store;
+
+//Sits in the root of every installation so later commands know what's in it
+pub const MANIFEST_FILE: &str = "instance.json";
+//The version json vanilla installs keep, for launchers
+pub const VERSION_FILE: &str = "version_info.json";
+
+pub const INSTALLATIONS_DIR: &str = "./installations";
+pub const SERVERS_DIR: &str = "./servers";
+
+//Clients and servers live apart, but share one set of names
+pub fn instance_dir(name: &str, side: Side) -> PathBuf {
+    let root = match side {
+        Side::Client => INSTALLATIONS_DIR,
+        Side::Server => SERVERS_DIR,
+    };
+    Path::new(root).join(name)
+}
+
+//Clients share ./libraries, servers keep their own so they can be moved around
+pub fn library_dir(directory: &Path, side: Side) -> PathBuf {
+    match side {
+        Side::Client => PathBuf::from("./libraries"),
+        Side::Server => directory.join("libraries"),
+    }
+}
+
+//Names end up as folder names, so they can't leave the folder they're in
+pub fn validate_name(name: &str) -> Result<(), InstallError> {
+    if name.is_empty() || name.starts_with('.') || name.contains(|c: char| c == '/' || c == '\\' || c.is_control()) {
+        return Err(InstallError::Unsupported(format!("{:?} can't be used as an instance name", name)));
+    }
+    Ok(())
+}
+
+//Turns something like a modpack's name into a usable instance name
+pub fn name_from(text: &str) -> String {
+    let name: String = text.trim()
+                           .chars()
+                           .map(|c| if c == '/' || c == '\\' || c.is_control() { '-' } else { c })
+                           .collect();
+    let name = name.trim_start_matches('.');
+    if name.is_empty() { String::from("instance") } else { String::from(name) }
+}
+
+//Looks through both clients and servers
+pub fn find(name: &str) -> Result<(PathBuf, InstanceManifest), InstallError> {
+    validate_name(name)?;
+    for side in &[Side::Client, Side::Server] {
+        let directory = instance_dir(name, *side);
+        if directory.join(MANIFEST_FILE).is_file() {
+            let manifest = InstanceManifest::load(&directory)?;
+            return Ok((directory, manifest));
+        }
+    }
+    Err(InstallError::Unsupported(format!("there's no instance called {}", name)))
+}
+
+pub fn exists(name: &str) -> bool {
+    [Side::Client, Side::Server].iter().any(|side| instance_dir(name, *side).exists())
+}
+
+//Every instance there is, clients first, each sorted by name
+pub fn list() -> Result<Vec<(PathBuf, InstanceManifest)>, InstallError> {
+    let mut instances = Vec::new();
+    for root in &[INSTALLATIONS_DIR, SERVERS_DIR] {
+        let entries = match std::fs::read_dir(root) {
+            Ok(entries) => entries,
+            Err(e) if e.kind() == std::io::ErrorKind::NotFound => continue,
+            Err(e) => return Err(e.into()),
+        };
+        let mut found = Vec::new();
+        for entry in entries {
+            let directory = entry?.path();
+            if directory.join(MANIFEST_FILE).is_file() {
+                let manifest = InstanceManifest::load(&directory)?;
+                found.push((directory, manifest));
+            }
+        }
+        found.sort_by(|a, b| a.1.name.cmp(&b.1.name));
+        instances.extend(found);
+    }
+    Ok(instances)
+}
+
+pub fn rename(name: &str, new_name: &str) -> Result<PathBuf, InstallError> {
+    validate_name(new_name)?;
+    if exists(new_name) {
+        return Err(InstallError::Unsupported(format!("there's already an instance called {}", new_name)));
+    }
+    let (directory, mut manifest) = find(name)?;
+    let new_directory = instance_dir(new_name, manifest.side);
+    std::fs::rename(&directory, &new_directory)?;
+    manifest.name = String::from(new_name);
+    manifest.save(&new_directory)?;
+    Ok(new_directory)
+}
+
+//Copies the whole instance, worlds and all. Libraries and assets are shared anyway, and anything
+//from the store stays shared.
+pub fn clone(name: &str, new_name: &str) -> Result<PathBuf, InstallError> {
+    validate_name(new_name)?;
+    if exists(new_name) {
+        return Err(InstallError::Unsupported(format!("there's already an instance called {}", new_name)));
+    }
+    let (directory, mut manifest) = find(name)?;
+    let new_directory = instance_dir(new_name, manifest.side);
+    copy_dir(&directory, &new_directory, &store::Links::scan()?)?;
+    manifest.name = String::from(new_name);
+    manifest.created = now();
+    manifest.save(&new_directory)?;
+    Ok(new_directory)
+}
+
+pub fn delete(name: &str) -> Result<PathBuf, InstallError> {
+    let (directory, _) = find(name)?;
+    std::fs::remove_dir_all(&directory)?;
+    Ok(directory)
+}
+
+fn copy_dir(from: &Path, to: &Path, links: &store::Links) -> Result<(), InstallError> {
+    std::fs::create_dir_all(to)?;
+    for entry in std::fs::read_dir(from)? {
+        let entry = entry?;
+        let target = to.join(entry.file_name());
+        if entry.file_type()?.is_dir() {
+            copy_dir(&entry.path(), &target, links)?;
+        } else if let Some(blob) = links.blob_of(&entry.path())? {
+            //Jars that came out of the store get linked to it again rather than copied
+            store::link_or_copy(&blob, &target)?;
+        } else {
+            //Copied rather than written, so start scripts stay executable
+            std::fs::copy(entry.path(), &target)?;
+        }
+    }
+    Ok(())
+}
+
+fn now() -> String {
+    chrono::Utc::now().to_rfc3339()
+}
+
+//The version json for a minecraft version, along with what a lockfile needs to pin it
+pub fn get_version_data(mc_version: &str) -> Result<(MojangVersionData, LockedFile), InstallError> {
+    let manifest = MojangVersionManifest::fetch()?;
+    let version = manifest.look_up_version(String::from(mc_version)).ok_or_else(|| {
+        InstallError::Unsupported(format!("there's no minecraft version called {}", mc_version))
+    })?;
+    fetch_version_data(&version.url)
+}
+
+pub fn fetch_version_data(url: &str) -> Result<(MojangVersionData, LockedFile), InstallError> {
+    println!("downloading from {}", url);
+    let bytes = download::download_and_check(&String::from(url), None)?;
+    let version = LockedFile {
+        path: String::from(VERSION_FILE),
+        url: Some(String::from(url)),
+        sha1: sha1::Sha1::from(&bytes).digest().to_string(),
+        sha512: None,
+    };
+    Ok((serde_json::from_slice(&bytes)?, version))
+}
+
+//Installs vanilla and then the loader, if there is one, into the named instance and records both
+//in its manifest and lockfile. Everything that ends up with an instance goes through here. Java
+//settings, when given, replace the instance's before a server's start scripts get written.
+pub fn install(name: &str, mc_version: &str, loader_build: Option<&(Box<dyn ModLoader>, LoaderBuild)>, side: Side, accept_eula: bool, java: Option<JavaSettings>) -> Result<(PathBuf, InstanceManifest), InstallError> {
+    let (vanilla, version) = get_version_data(mc_version)?;
+    let (directory, manifest, lock) = install_version(name, &vanilla, version, loader_build, side, accept_eula, java)?;
+    lock.save(&directory)?;
+    Ok((directory, manifest))
+}
+
+//What install would download, without writing anything. Only the version json and, when it isn't
+//there yet, the asset index get fetched to work that out.
+pub fn plan(name: &str, mc_version: &str, loader_build: Option<&(Box<dyn ModLoader>, LoaderBuild)>, side: Side) -> Result<DownloadPlan, InstallError> {
+    validate_name(name)?;
+    check_supported(loader_build, side)?;
+    let (vanilla, _) = get_version_data(mc_version)?;
+    let mut plan = plan::plan_install(&vanilla, &instance_dir(name, side), side)?;
+    plan.loader = loader_build.map(|(loader, build)| InstanceLoader {
+        name: String::from(loader.name()),
+        version: build.version.clone(),
+    });
+    Ok(plan)
+}
+
+//Same as install but with the version json already fetched. The lockfile for what got installed
+//is left to the caller to save or compare.
+pub fn install_version(name: &str, vanilla: &MojangVersionData, version: LockedFile, loader_build: Option<&(Box<dyn ModLoader>, LoaderBuild)>, side: Side, accept_eula: bool, java: Option<JavaSettings>) -> Result<(PathBuf, InstanceManifest, Lockfile), InstallError> {
+    validate_name(name)?;
+    let directory = instance_dir(name, side);
+    //The same name on the other side would make the name ambiguous
+    if instance_dir(name, other_side(side)).exists() {
+        return Err(InstallError::Unsupported(format!("there's already an instance called {}", name)));
+    }
+    check_supported(loader_build, side)?;
+
+    //Start scripts take their java settings from the manifest
+    if let Some(java) = java {
+        let mut manifest = InstanceManifest::load_or_new(&directory, name, &vanilla.id, side)?;
+        manifest.java = java;
+        manifest.save(&directory)?;
+    }
+
+    match side {
+        Side::Client => download::install_to_directory(vanilla, &directory)?,
+        Side::Server => server::install_server_to_directory(vanilla, &directory, accept_eula)?,
+    }
+
+    if let Some((loader, build)) = loader_build {
+        println!("Installing {} {}", loader.name(), build.version);
+        loader.install(build, vanilla, &directory, side)?;
+    }
+
+    let mut manifest = InstanceManifest::load_or_new(&directory, name, &vanilla.id, side)?;
+    manifest.loader = loader_build.map(|(loader, build)| InstanceLoader {
+        name: String::from(loader.name()),
+        version: build.version.clone(),
+    });
+    manifest.save(&directory)?;
+    let lock = Lockfile::generate(&directory, &manifest, vanilla, version)?;
+    Ok((directory, manifest, lock))
+}
+
+//Checked before anything gets downloaded, so an unsupported loader doesn't leave half an instance
+fn check_supported(loader_build: Option<&(Box<dyn ModLoader>, LoaderBuild)>, side: Side) -> Result<(), InstallError> {
+    match loader_build {
+        Some((loader, _)) if !loader.supports(side) => {
+            Err(InstallError::Unsupported(format!("{} can't be installed on a {}", loader.name(), side.name())))
+        }
+        _ => Ok(()),
+    }
+}
+
+fn other_side(side: Side) -> Side {
+    match side {
+        Side::Client => Side::Server,
+        Side::Server => Side::Client,
+    }
+}
+
+#[derive(Serialize, Deserialize, Debug, Clone)]
+pub struct InstanceManifest {
+    //Older manifests don't have one, so it comes from the folder when they're loaded
+    #[serde(default)]
+    pub name: String,
+    #[serde(default)]
+    pub side: Side,
+    pub minecraft: String,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub loader: Option<InstanceLoader>,
+    #[serde(default)]
+    pub java: JavaSettings,
+    #[serde(default)]
+    pub mods: Vec<InstalledMod>,
+    //The modpack this instance came from, if any
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub pack: Option<PackInfo>,
+    //Files a modpack had downloaded into the instance
+    #[serde(default, skip_serializing_if = "Vec::is_empty")]
+    pub files: Vec<PackFile>,
+    //What a packwiz pack last installed, so updates only touch what changed
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub packwiz: Option<PackwizState>,
+    //RFC 3339, both of them
+    #[serde(default)]
+    pub created: String,
+    #[serde(default)]
+    pub updated: String,
+}
+
+//How the instance wants java run. Empty means whatever the launcher picks.
+#[derive(Serialize, Deserialize, Debug, Clone, Default, PartialEq)]
+pub struct JavaSettings {
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub path: Option<String>,
+    //As -Xmx takes it, i.e. 4G
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub memory: Option<String>,
+    #[serde(default, skip_serializing_if = "Vec::is_empty")]
+    pub args: Vec<String>,
+}
+
+impl JavaSettings {
+    //Everything to pass to java ahead of the main class or jar
+    pub fn jvm_args(&self) -> Vec<String> {
+        self.memory.iter()
+                   .map(|memory| format!("-Xmx{}", memory))
+                   .chain(self.args.iter().cloned())
+                   .collect()
+    }
+}
+
+#[derive(Serialize, Deserialize, Debug, Clone)]
+pub struct PackwizState {
+    pub source: String,
+    pub index_hash: String,
+    //What the import asked for, since that decides which metafiles got installed
+    #[serde(default)]
+    pub side: Option<Side>,
+    #[serde(default)]
+    pub include_optional: bool,
+    //Keyed by the file's path in the index
+    pub entries: BTreeMap<String, PackwizEntry>,
+}
+
+#[derive(Serialize, Deserialize, Debug, Clone)]
+pub struct PackwizEntry {
+    //The hash the index gave it
+    pub hash: String,
+    //Where it ended up in the instance, which for metafiles is wherever their download went
+    pub path: String,
+}
+
+#[derive(Serialize, Deserialize, Debug, Clone, PartialEq)]
+pub struct InstanceLoader {
+    pub name: String,
+    pub version: String,
+}
+
+#[derive(Serialize, Deserialize, Debug, Clone)]
+pub struct InstalledMod {
+    pub project_id: String,
+    pub slug: String,
+    pub version_id: String,
+    pub version_number: String,
+    pub file_name: String,
+    pub url: String,
+    pub sha512: String,
+}
+
+#[derive(Serialize, Deserialize, Debug, Clone)]
+pub struct PackInfo {
+    pub name: String,
+    pub version: String,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub summary: Option<String>,
+}
+
+#[derive(Serialize, Deserialize, Debug, Clone)]
+pub struct PackFile {
+    //Relative to the instance
+    pub path: String,
+    pub downloads: Vec<String>,
+    pub sha1: String,
+    pub sha512: String,
+    pub size: u64,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub env: Option<FileEnv>,
+}
+
+//Whether each side needs a file: required, optional or unsupported
+#[derive(Serialize, Deserialize, Debug, Clone, PartialEq)]
+pub struct FileEnv {
+    pub client: String,
+    pub server: String,
+}
+
+impl FileEnv {
+    pub fn for_side(&self, side: Side) -> &str {
+        match side {
+            Side::Client => &self.client,
+            Side::Server => &self.server,
+        }
+    }
+}
+
+impl InstanceManifest {
+    pub fn new(name: &str, minecraft: &str, side: Side) -> Self {
+        InstanceManifest {
+            name: String::from(name),
+            side,
+            minecraft: String::from(minecraft),
+            loader: None,
+            java: JavaSettings::default(),
+            mods: Vec::new(),
+            pack: None,
+            files: Vec::new(),
+            packwiz: None,
+            created: now(),
+            updated: now(),
+        }
+    }
+
+    pub fn load(directory: &Path) -> Result<Self, InstallError> {
+        let file = match std::fs::read(directory.join(MANIFEST_FILE)) {
+            Ok(file) => file,
+            Err(e) if e.kind() == std::io::ErrorKind::NotFound => {
+                return Err(InstallError::Unsupported(
+                        format!("{} isn't an installation, install it first", directory.display())));
+            }
+            Err(e) => return Err(e.into()),
+        };
+        let mut manifest: InstanceManifest = serde_json::from_slice(&file)?;
+        if manifest.name.is_empty() {
+            manifest.name = directory.file_name()
+                                     .map(|name| name.to_string_lossy().into_owned())
+                                     .unwrap_or_default();
+        }
+        Ok(manifest)
+    }
+
+    //Keeps the mods of an existing manifest when an installation is redone
+    pub fn load_or_new(directory: &Path, name: &str, minecraft: &str, side: Side) -> Result<Self, InstallError> {
+        match Self::load(directory) {
+            Ok(mut manifest) => {
+                manifest.minecraft = String::from(minecraft);
+                manifest.side = side;
+                Ok(manifest)
+            }
+            Err(InstallError::Unsupported(_)) => Ok(Self::new(name, minecraft, side)),
+            Err(e) => Err(e),
+        }
+    }
+
+    pub fn save(&mut self, directory: &Path) -> Result<(), InstallError> {
+        self.updated = now();
+        if self.created.is_empty() {
+            self.created = self.updated.clone();
+        }
+        std::fs::create_dir_all(directory)?;
+        std::fs::write(directory.join(MANIFEST_FILE), serde_json::to_string_pretty(self)?)?;
+        Ok(())
+    }
+
+    //Replaces any other version of the same project
+    pub fn add_mod(&mut self, installed: InstalledMod) -> Option<InstalledMod> {
+        let old = self.mods.iter()
+                           .position(|m| m.project_id == installed.project_id)
+                           .map(|i| self.mods.remove(i));
+        self.mods.push(installed);
+        old
+    }
+}
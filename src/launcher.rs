@@ -0,0 +1,112 @@
+use std::path::{Path, PathBuf};
+use super::download::{self, InstallError, download_and_check, try_download_verified};
+use super::mc_data::MojangVersionManifest;
+use super::mc_data::mojang_version_data::MojangVersionData;
+use super::modloader::{self, ModLoader, LoaderBuild, ProfileLibrary, Side};
+
+//Where the official launcher keeps its profiles, in the root of .minecraft
+pub const PROFILES_FILE: &str = "launcher_profiles.json";
+pub const VERSIONS_DIR: &str = "versions";
+
+//Where the official launcher puts .minecraft on this OS
+pub fn default_minecraft_dir() -> Result<PathBuf, InstallError> {
+    let var = |name: &str| std::env::var_os(name).map(PathBuf::from).ok_or_else(|| {
+        InstallError::Unsupported(format!("${} isn't set, so there's no default .minecraft", name))
+    });
+    Ok(match std::env::consts::OS {
+        "windows" => var("APPDATA")?.join(".minecraft"),
+        "macos" => var("HOME")?.join("Library").join("Application Support").join("minecraft"),
+        _ => var("HOME")?.join(".minecraft"),
+    })
+}
+
+//Installs a version into an existing .minecraft the way the official launcher lays it out:
+//versions/<id>/<id>.jar and <id>.json, with its assets and libraries stores. The loader, if there
+//is one, becomes its own version inheriting from vanilla. Then a profile called name gets added
+//to launcher_profiles.json. Returns the version id the profile launches.
+pub fn install(minecraft_dir: &Path, name: &str, mc_version: &str, loader_build: Option<&(Box<dyn ModLoader>, LoaderBuild)>) -> Result<String, InstallError> {
+    if !minecraft_dir.is_dir() {
+        return Err(InstallError::Unsupported(format!("{} doesn't exist, run the launcher once first", minecraft_dir.display())));
+    }
+    if let Some((loader, _)) = loader_build.filter(|(loader, _)| !loader.launcher_ready()) {
+        return Err(InstallError::Unsupported(
+                format!("{} can't be installed into .minecraft yet, use its own installer", loader.name())));
+    }
+
+    let manifest = MojangVersionManifest::fetch()?;
+    let version = manifest.look_up_version(String::from(mc_version)).ok_or_else(|| {
+        InstallError::Unsupported(format!("there's no minecraft version called {}", mc_version))
+    })?;
+    //The launcher reads fields we don't parse, so the json is kept as it came
+    println!("downloading from {}", &version.url);
+    let version_json = download_and_check(&version.url, None)?;
+    let vanilla: MojangVersionData = serde_json::from_slice(&version_json)?;
+
+    let client = reqwest::blocking::Client::new();
+    download::install_assets(&vanilla, &minecraft_dir.join("assets"), &client)?;
+    download::install_libraries(&vanilla, &minecraft_dir.join("libraries"), &client)?;
+
+    let version_dir = minecraft_dir.join(VERSIONS_DIR).join(&vanilla.id);
+    try_download_verified(&vanilla.downloads.client.url,
+                          &vanilla.downloads.client.sha1,
+                          &version_dir,
+                          &format!("{}.jar", vanilla.id),
+                          Some(&client))?;
+    std::fs::write(version_dir.join(format!("{}.json", vanilla.id)), &version_json)?;
+
+    let version_id = match loader_build {
+        Some((loader, build)) => install_loader_profile(minecraft_dir, loader.as_ref(), build, &client)?,
+        None => vanilla.id.clone(),
+    };
+
+    add_profile(minecraft_dir, name, &version_id)?;
+    Ok(version_id)
+}
+
+fn install_loader_profile(minecraft_dir: &Path, loader: &dyn ModLoader, build: &LoaderBuild, client: &reqwest::blocking::Client) -> Result<String, InstallError> {
+    let profile = loader.profile(build, Side::Client)?;
+    let id = profile["id"].as_str()
+                          .map(String::from)
+                          .ok_or_else(|| InstallError::Unsupported(format!("{} {} has no profile id", loader.name(), build.version)))?;
+    let libraries: Vec<ProfileLibrary> = serde_json::from_value(profile["libraries"].clone())?;
+    modloader::install_libraries_to(&libraries, &minecraft_dir.join("libraries"), client)?;
+
+    let profile_dir = minecraft_dir.join(VERSIONS_DIR).join(&id);
+    std::fs::create_dir_all(&profile_dir)?;
+    std::fs::write(profile_dir.join(format!("{}.json", id)), serde_json::to_string_pretty(&profile)?)?;
+    Ok(id)
+}
+
+//Adds or replaces a profile, keeping everything else in the file as the launcher left it
+pub fn add_profile(minecraft_dir: &Path, name: &str, version_id: &str) -> Result<(), InstallError> {
+    let path = minecraft_dir.join(PROFILES_FILE);
+    let mut profiles: serde_json::Value = match std::fs::read(&path) {
+        Ok(bytes) => serde_json::from_slice(&bytes)?,
+        Err(e) if e.kind() == std::io::ErrorKind::NotFound => serde_json::json!({ "profiles": {}, "settings": {}, "version": 3 }),
+        Err(e) => return Err(e.into()),
+    };
+
+    //The launcher's own timestamps have milliseconds and a Z
+    let now = chrono::Utc::now().format("%Y-%m-%dT%H:%M:%S%.3fZ").to_string();
+    let entries = profiles.as_object_mut()
+                          .ok_or_else(|| InstallError::Unsupported(format!("{} isn't a json object", path.display())))?
+                          .entry("profiles")
+                          .or_insert_with(|| serde_json::json!({}))
+                          .as_object_mut()
+                          .ok_or_else(|| InstallError::Unsupported(format!("profiles in {} isn't a json object", path.display())))?;
+    let created = entries.get(name)
+                         .and_then(|profile| profile["created"].as_str())
+                         .map(String::from)
+                         .unwrap_or_else(|| now.clone());
+    entries.insert(String::from(name), serde_json::json!({
+        "name": name,
+        "type": "custom",
+        "created": created,
+        "lastUsed": now,
+        "lastVersionId": version_id,
+        "icon": "Grass",
+    }));
+
+    std::fs::write(&path, serde_json::to_string_pretty(&profiles)?)?;
+    Ok(())
+}
@@ -1,4 +1,15 @@
 pub mod mc_data;
 pub mod types;
 pub mod download;
+pub mod maven;
+pub mod server;
 pub mod modloader;
+pub mod instance;
+pub mod modrinth;
+pub mod modpack;
+pub mod lockfile;
+pub mod launcher;
+pub mod store;
+pub mod plan;
+pub mod gc;
+pub mod verify;
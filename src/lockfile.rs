@@ -0,0 +1,414 @@
+use std::collections::BTreeMap;
+use std::path::{Path, PathBuf};
+use serde::{Serialize, Deserialize};
+use sha2::Digest;
+use super::download::{self, FileKind, InstallError, download_and_check};
+use super::instance::{self, InstanceLoader, InstanceManifest};
+use super::maven::MavenCoordinate;
+use super::mc_data::mojang_version_data::MojangVersionData;
+use super::modloader::{self, Side, PROFILE_FILE};
+use super::modrinth::{self, MODS_DIR};
+use super::plan::{self, DownloadPlan};
+use super::server::SERVER_JAR;
+use super::store;
+
+//Sits next to instance.json and pins everything installing the instance downloaded
+pub const LOCK_FILE: &str = "instance.lock";
+
+#[derive(Serialize, Deserialize, Debug, Clone, PartialEq)]
+pub struct Lockfile {
+    pub minecraft: String,
+    //The version json itself, so a re-uploaded version gets noticed
+    pub version: LockedFile,
+    pub asset_index: LockedFile,
+    //client.jar or server.jar
+    pub game_jar: LockedFile,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub loader: Option<InstanceLoader>,
+    //Paths are inside the libraries folder, everything else is inside the instance
+    pub libraries: Vec<LockedFile>,
+    #[serde(default)]
+    pub mods: Vec<LockedFile>,
+}
+
+#[derive(Serialize, Deserialize, Debug, Clone, PartialEq)]
+pub struct LockedFile {
+    pub path: String,
+    //Files the loader installer makes itself have nowhere to download them from
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub url: Option<String>,
+    pub sha1: String,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub sha512: Option<String>,
+}
+
+impl Lockfile {
+    //Pins what's installed in the directory right now. Only files that are actually there get
+    //pinned, so comparing against an older lockfile shows what went missing.
+    pub fn generate(directory: &Path, manifest: &InstanceManifest, vanilla: &MojangVersionData, version: LockedFile) -> Result<Self, InstallError> {
+        let game_jar = match manifest.side {
+            Side::Client => LockedFile {
+                path: String::from("client.jar"),
+                url: Some(vanilla.downloads.client.url.clone()),
+                sha1: vanilla.downloads.client.sha1.clone(),
+                sha512: None,
+            },
+            Side::Server => {
+                let server = vanilla.downloads.server.as_ref().ok_or_else(|| {
+                    InstallError::Unsupported(format!("{} has no dedicated server download", vanilla.id))
+                })?;
+                LockedFile {
+                    path: String::from(SERVER_JAR),
+                    url: Some(server.url.clone()),
+                    sha1: server.sha1.clone(),
+                    sha512: None,
+                }
+            }
+        };
+
+        let lib_dir = instance::library_dir(directory, manifest.side);
+        let mut libraries = BTreeMap::new();
+        //Servers run off the server jar, so only clients get the vanilla libraries
+        if manifest.side == Side::Client {
+            let (libs, natives) = download::get_needed_libraries(vanilla);
+            for artifact in libs.iter().chain(natives.iter()) {
+                if let Some(path) = &artifact.path {
+                    libraries.insert(path.clone(), Some(artifact.url.clone()));
+                }
+            }
+        }
+        if manifest.loader.is_some() {
+            for (path, url) in profile_libraries(directory)? {
+                libraries.entry(path).or_insert(url);
+            }
+        }
+        let libraries = libraries.into_iter()
+                                 .filter_map(|(path, url)| hash_file(&lib_dir.join(&path), &path, url, false).transpose())
+                                 .collect::<Result<Vec<LockedFile>, InstallError>>()?;
+
+        Ok(Lockfile {
+            minecraft: vanilla.id.clone(),
+            version,
+            asset_index: LockedFile {
+                path: vanilla.asset_index.id.clone(),
+                url: Some(vanilla.asset_index.url.clone()),
+                sha1: vanilla.asset_index.sha1.clone(),
+                sha512: None,
+            },
+            game_jar,
+            loader: manifest.loader.clone(),
+            libraries,
+            mods: lock_mods(directory, manifest)?,
+        })
+    }
+
+    pub fn load(directory: &Path) -> Result<Self, InstallError> {
+        let path = directory.join(LOCK_FILE);
+        if !path.exists() {
+            return Err(InstallError::Unsupported(format!("{} has no lockfile, run update to make one", directory.display())));
+        }
+        Ok(serde_json::from_slice(&std::fs::read(path)?)?)
+    }
+
+    pub fn save(&self, directory: &Path) -> Result<(), InstallError> {
+        std::fs::write(directory.join(LOCK_FILE), serde_json::to_string_pretty(self)?)?;
+        Ok(())
+    }
+
+    //What changed going from self to other, one line each
+    pub fn differences(&self, other: &Lockfile) -> Vec<String> {
+        let mut differences = Vec::new();
+        if self.minecraft != other.minecraft {
+            differences.push(format!("minecraft {} -> {}", self.minecraft, other.minecraft));
+        }
+        if self.loader != other.loader {
+            let describe = |loader: &Option<InstanceLoader>| match loader {
+                Some(loader) => format!("{} {}", loader.name, loader.version),
+                None => String::from("no loader"),
+            };
+            differences.push(format!("loader {} -> {}", describe(&self.loader), describe(&other.loader)));
+        }
+        compare_file("version json", &self.version, &other.version, &mut differences);
+        compare_file("asset index", &self.asset_index, &other.asset_index, &mut differences);
+        compare_file("game jar", &self.game_jar, &other.game_jar, &mut differences);
+        compare_files("library", &self.libraries, &other.libraries, &mut differences);
+        compare_files("file", &self.mods, &other.mods, &mut differences);
+        differences
+    }
+}
+
+fn compare_file(what: &str, old: &LockedFile, new: &LockedFile, differences: &mut Vec<String>) {
+    if old.sha1 != new.sha1 || old.path != new.path {
+        differences.push(format!("{} {} ({}) -> {} ({})", what, old.path, old.sha1, new.path, new.sha1));
+    }
+}
+
+fn compare_files(what: &str, old: &[LockedFile], new: &[LockedFile], differences: &mut Vec<String>) {
+    let old: BTreeMap<&str, &str> = old.iter().map(|f| (f.path.as_str(), f.sha1.as_str())).collect();
+    let new: BTreeMap<&str, &str> = new.iter().map(|f| (f.path.as_str(), f.sha1.as_str())).collect();
+    for (path, sha1) in &old {
+        match new.get(path) {
+            None => differences.push(format!("{} {} removed", what, path)),
+            Some(new_sha1) if new_sha1 != sha1 => differences.push(format!("{} {} changed ({} -> {})", what, path, sha1, new_sha1)),
+            _ => (),
+        }
+    }
+    for path in new.keys().filter(|path| !old.contains_key(*path)) {
+        differences.push(format!("{} {} added", what, path));
+    }
+}
+
+//Every loader writes a profile listing its libraries, though they don't agree on where the url
+//goes: forge has downloads.artifact, fabric and old forge have the repository in url
+pub fn profile_libraries(directory: &Path) -> Result<Vec<(String, Option<String>)>, InstallError> {
+    let profile: serde_json::Value = serde_json::from_slice(&std::fs::read(directory.join(PROFILE_FILE))?)?;
+    let mut libraries = Vec::new();
+    for library in profile["libraries"].as_array().into_iter().flatten() {
+        let artifact = &library["downloads"]["artifact"];
+        let path = match artifact["path"].as_str() {
+            Some(path) => String::from(path),
+            None => match library["name"].as_str() {
+                Some(name) => name.parse::<MavenCoordinate>()?.path(),
+                None => continue,
+            },
+        };
+        let url = match artifact["url"].as_str() {
+            Some(url) => Some(String::from(url)),
+            None => library["url"].as_str().map(|repository| {
+                let repository = repository.trim_end_matches('/');
+                format!("{}/{}", repository, path)
+            }),
+        };
+        libraries.push((path, url.filter(|url| !url.is_empty())));
+    }
+    Ok(libraries)
+}
+
+//Modrinth mods first, then whatever a modpack put in
+fn lock_mods(directory: &Path, manifest: &InstanceManifest) -> Result<Vec<LockedFile>, InstallError> {
+    let files = manifest.mods.iter()
+                             .map(|m| (format!("{}/{}", MODS_DIR, m.file_name), Some(m.url.clone())))
+                             .chain(manifest.files.iter().map(|f| (f.path.clone(), f.downloads.first().cloned())));
+    let mut locked: Vec<LockedFile> = Vec::new();
+    for (path, url) in files {
+        if locked.iter().any(|f| f.path == path) {
+            continue;
+        }
+        if let Some(file) = hash_file(&directory.join(&path), &path, url, true)? {
+            locked.push(file);
+        }
+    }
+    Ok(locked)
+}
+
+fn hash_file(location: &Path, path: &str, url: Option<String>, with_sha512: bool) -> Result<Option<LockedFile>, InstallError> {
+    let bytes = match std::fs::read(location) {
+        Ok(bytes) => bytes,
+        Err(e) if e.kind() == std::io::ErrorKind::NotFound => return Ok(None),
+        Err(e) => return Err(e.into()),
+    };
+    Ok(Some(LockedFile {
+        path: String::from(path),
+        url,
+        sha1: sha1::Sha1::from(&bytes).digest().to_string(),
+        sha512: if with_sha512 { Some(format!("{:x}", sha2::Sha512::digest(&bytes))) } else { None },
+    }))
+}
+
+//Reinstalls exactly what the instance's lockfile says. Anything that can't be had any more, or
+//comes out different, is an error instead of a quiet upgrade. Everything pinned is downloaded and
+//checked before the instance is touched, so only files a loader's installer makes itself can still
+//turn out different afterwards.
+pub fn install_locked(name: &str, accept_eula: bool) -> Result<PathBuf, InstallError> {
+    let (directory, manifest) = instance::find(name)?;
+    let lock = Lockfile::load(&directory)?;
+
+    let version_url = lock.version.url.as_deref().ok_or_else(|| {
+        InstallError::Unsupported(format!("{} has no version url", LOCK_FILE))
+    })?;
+    let (vanilla, version) = instance::fetch_version_data(version_url)?;
+    if version.sha1 != lock.version.sha1 {
+        return Err(InstallError::HashError(format!("the version json for {} has sha1 {}, expected {}",
+                                                   lock.minecraft, version.sha1, lock.version.sha1)));
+    }
+    if vanilla.asset_index.sha1 != lock.asset_index.sha1 {
+        return Err(InstallError::HashError(format!("asset index {} has sha1 {}, expected {}",
+                                                   lock.asset_index.path, vanilla.asset_index.sha1, lock.asset_index.sha1)));
+    }
+    let loader_build = match &lock.loader {
+        Some(loader) => Some(modloader::resolve_loader(&format!("{}@{}", loader.name, loader.version), &vanilla.id)?),
+        None => None,
+    };
+
+    let differences = compare_plan(&lock, &plan::plan_install(&vanilla, &directory, manifest.side)?);
+    if !differences.is_empty() {
+        return Err(InstallError::HashError(format!("{} doesn't match its lockfile:\n  {}", name, differences.join("\n  "))));
+    }
+    for file in fetch_locked(&directory, &lock)? {
+        store::link(&file.sha1, &directory.join(&file.path))?;
+    }
+    let (directory, _, installed) = instance::install_version(name, &vanilla, version, loader_build.as_ref(), manifest.side, accept_eula, None)?;
+
+    let differences = lock.differences(&installed);
+    if !differences.is_empty() {
+        return Err(InstallError::HashError(format!("{} doesn't match its lockfile:\n  {}", name, differences.join("\n  "))));
+    }
+    Ok(directory)
+}
+
+//What installing vanilla would put in the instance that the lockfile doesn't pin the same way
+fn compare_plan(lock: &Lockfile, plan: &DownloadPlan) -> Vec<String> {
+    let mut differences = Vec::new();
+    for file in &plan.files {
+        let (what, locked) = match file.kind {
+            FileKind::GameJar => ("game jar", Some(&lock.game_jar).filter(|jar| file.destination.ends_with(&jar.path))),
+            FileKind::Library | FileKind::Native => ("library", lock.libraries.iter().find(|library| file.destination.ends_with(&library.path))),
+            _ => continue,
+        };
+        let sha1 = file.sha1.as_deref().unwrap_or_default();
+        match locked {
+            None => differences.push(format!("{} {} isn't in the lockfile", what, file.destination.display())),
+            Some(locked) if locked.sha1 != sha1 => differences.push(format!("{} {} changed ({} -> {})", what, locked.path, locked.sha1, sha1)),
+            _ => (),
+        }
+    }
+    differences
+}
+
+//Gets every pinned file into the store, refusing anything that doesn't match its hashes. Nothing
+//in the instance changes. Returns the mods that aren't already in place.
+fn fetch_locked<'a>(directory: &Path, lock: &'a Lockfile) -> Result<Vec<&'a LockedFile>, InstallError> {
+    let client = reqwest::blocking::Client::new();
+    for file in std::iter::once(&lock.game_jar).chain(&lock.libraries) {
+        //Files the loader's installer makes itself are only compared afterwards
+        if let Some(url) = &file.url {
+            store::fetch_blob(url, &file.sha1, Some(&client))?;
+        }
+    }
+
+    let mut mods = Vec::new();
+    for file in &lock.mods {
+        if let Ok(bytes) = std::fs::read(directory.join(&file.path)) {
+            if sha1::Sha1::from(&bytes).digest().to_string() == file.sha1 {
+                continue;
+            }
+        }
+        mods.push(file);
+        if store::is_intact(&file.sha1) {
+            continue;
+        }
+        let url = file.url.as_ref().ok_or_else(|| {
+            InstallError::Unsupported(format!("{} is missing and has nowhere to download it from", file.path))
+        })?;
+        let bytes = download_and_check(url, Some(&client))?;
+        let sha1 = sha1::Sha1::from(&bytes).digest().to_string();
+        if sha1 != file.sha1 {
+            return Err(InstallError::HashError(format!("{} has sha1 {}, expected {}", url, sha1, file.sha1)));
+        }
+        if let Some(expected) = &file.sha512 {
+            let sha512 = format!("{:x}", sha2::Sha512::digest(&bytes));
+            if &sha512 != expected {
+                return Err(InstallError::HashError(format!("{} has sha512 {}, expected {}", url, sha512, expected)));
+            }
+        }
+        store::insert(&bytes)?;
+        println!("downloaded {}", file.path);
+    }
+    Ok(mods)
+}
+
+//Re-pins the mods after they've changed, leaving the rest of the lockfile alone
+pub fn update_mods(directory: &Path, manifest: &InstanceManifest) -> Result<(), InstallError> {
+    if !directory.join(LOCK_FILE).exists() {
+        return Ok(());
+    }
+    let mut lock = Lockfile::load(directory)?;
+    lock.mods = lock_mods(directory, manifest)?;
+    lock.save(directory)
+}
+
+//Moves the instance onto the newest stable loader build and the newest compatible version of
+//each modrinth mod, then writes a new lockfile. Returns what changed.
+pub fn update(name: &str) -> Result<(PathBuf, Vec<String>), InstallError> {
+    let (directory, manifest) = instance::find(name)?;
+    let old = Lockfile::load(&directory).ok();
+
+    let loader_build = match &manifest.loader {
+        Some(loader) => Some(modloader::resolve_loader(&loader.name, &manifest.minecraft)?),
+        None => None,
+    };
+    let (directory, mut manifest) = instance::install(name, &manifest.minecraft, loader_build.as_ref(), manifest.side, false, None)?;
+
+    if !manifest.mods.is_empty() {
+        let client = modrinth::client()?;
+        for installed in manifest.mods.clone() {
+            let version = modrinth::newest_compatible(&client, &installed.project_id, &manifest)?;
+            if version.id != installed.version_id {
+                let project = modrinth::get_project(&client, &installed.project_id)?;
+                modrinth::install_version(&client, &project, &version, &directory, &mut manifest)?;
+                println!("Updated {} {} -> {}", installed.slug, installed.version_number, version.version_number);
+            }
+        }
+        manifest.save(&directory)?;
+        update_mods(&directory, &manifest)?;
+    }
+
+    let new = Lockfile::load(&directory)?;
+    let differences = match old {
+        Some(old) => old.differences(&new),
+        None => vec![String::from("new lockfile")],
+    };
+    Ok((directory, differences))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn file(path: &str, sha1: &str) -> LockedFile {
+        LockedFile { path: String::from(path), url: None, sha1: String::from(sha1), sha512: None }
+    }
+
+    fn lock(libraries: Vec<LockedFile>, mods: Vec<LockedFile>) -> Lockfile {
+        Lockfile {
+            minecraft: String::from("1.20.1"),
+            version: file("1.20.1.json", "v"),
+            asset_index: file("5.json", "i"),
+            game_jar: file("client.jar", "j"),
+            loader: Some(InstanceLoader { name: String::from("fabric"), version: String::from("0.14.21") }),
+            libraries,
+            mods,
+        }
+    }
+
+    #[test]
+    fn same_lock_has_no_differences() {
+        let old = lock(vec![file("a.jar", "1")], vec![file("mods/m.jar", "2")]);
+        assert!(old.differences(&old.clone()).is_empty());
+    }
+
+    #[test]
+    fn finds_added_removed_and_changed_files() {
+        let old = lock(vec![file("a.jar", "1"), file("b.jar", "2")], vec![file("mods/m.jar", "3")]);
+        let new = lock(vec![file("a.jar", "1"), file("c.jar", "4")], vec![file("mods/m.jar", "5")]);
+        assert_eq!(old.differences(&new), vec![
+            String::from("library b.jar removed"),
+            String::from("library c.jar added"),
+            String::from("file mods/m.jar changed (3 -> 5)"),
+        ]);
+    }
+
+    #[test]
+    fn finds_changed_versions_and_loader() {
+        let old = lock(Vec::new(), Vec::new());
+        let new = Lockfile {
+            game_jar: file("client.jar", "k"),
+            loader: None,
+            ..old.clone()
+        };
+        assert_eq!(old.differences(&new), vec![
+            String::from("loader fabric 0.14.21 -> no loader"),
+            String::from("game jar client.jar (j) -> client.jar (k)"),
+        ]);
+    }
+}
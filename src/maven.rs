@@ -0,0 +1,216 @@
+use super::download::InstallError;
+use std::fmt;
+use std::str::FromStr;
+
+//A maven artifact as libraries name them:
+//path.to.lib : unique-lib-name : version-identifier [: classifier] [@ extension]
+#[derive(Debug, Clone, PartialEq)]
+pub struct MavenCoordinate {
+    pub group: String,
+    pub artifact: String,
+    pub version: String,
+    pub classifier: Option<String>,
+    //jar unless the name says otherwise
+    pub extension: String,
+}
+
+impl MavenCoordinate {
+    pub fn file_name(&self) -> String {
+        match &self.classifier {
+            Some(classifier) => format!("{}-{}-{}.{}", self.artifact, self.version, classifier, self.extension),
+            None => format!("{}-{}.{}", self.artifact, self.version, self.extension),
+        }
+    }
+
+    //Where the artifact lives in a repository or in ./libraries
+    pub fn path(&self) -> String {
+        format!("{}/{}", self.version_path(), self.file_name())
+    }
+
+    pub fn url(&self, repository: &str) -> String {
+        format!("{}/{}", repository.trim_end_matches('/'), self.path())
+    }
+
+    //The same artifact with a different classifier, i.e. for natives
+    pub fn with_classifier(&self, classifier: &str) -> MavenCoordinate {
+        MavenCoordinate { classifier: Some(String::from(classifier)), ..self.clone() }
+    }
+
+    pub fn metadata_url(&self, repository: &str) -> String {
+        format!("{}/{}/maven-metadata.xml", repository.trim_end_matches('/'), self.artifact_path())
+    }
+
+    //What the repository knows about every version of the artifact
+    pub fn metadata(&self, repository: &str, client: &reqwest::blocking::Client) -> Result<MavenMetadata, InstallError> {
+        let xml = client.get(&self.metadata_url(repository))
+                        .send()?
+                        .error_for_status()?
+                        .text()?;
+        Ok(MavenMetadata::parse(&xml))
+    }
+
+    fn artifact_path(&self) -> String {
+        format!("{}/{}", self.group.replace('.', "/"), self.artifact)
+    }
+
+    fn version_path(&self) -> String {
+        format!("{}/{}", self.artifact_path(), self.version)
+    }
+}
+
+impl FromStr for MavenCoordinate {
+    type Err = InstallError;
+
+    fn from_str(name: &str) -> Result<Self, Self::Err> {
+        let (coordinate, extension) = match name.find('@') {
+            Some(at) => (&name[..at], &name[at + 1..]),
+            None => (name, "jar"),
+        };
+
+        let parts: Vec<&str> = coordinate.split(':').collect();
+        if parts.iter().any(|part| part.is_empty()) || extension.is_empty() {
+            return Err(InstallError::Unsupported(format!("{} is not a maven library name", name)));
+        }
+        let (group, artifact, version, classifier) = match parts.as_slice() {
+            [group, artifact, version] => (group, artifact, version, None),
+            [group, artifact, version, classifier] => (group, artifact, version, Some(String::from(*classifier))),
+            _ => return Err(InstallError::Unsupported(format!("{} is not a maven library name", name))),
+        };
+
+        Ok(MavenCoordinate {
+            group: String::from(*group),
+            artifact: String::from(*artifact),
+            version: String::from(*version),
+            classifier,
+            extension: String::from(extension),
+        })
+    }
+}
+
+impl fmt::Display for MavenCoordinate {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(f, "{}:{}:{}", self.group, self.artifact, self.version)?;
+        if let Some(classifier) = &self.classifier {
+            write!(f, ":{}", classifier)?;
+        }
+        if self.extension != "jar" {
+            write!(f, "@{}", self.extension)?;
+        }
+        Ok(())
+    }
+}
+
+//The bits of maven-metadata.xml worth knowing
+#[derive(Debug, Clone, Default)]
+pub struct MavenMetadata {
+    pub latest: Option<String>,
+    pub release: Option<String>,
+    //Oldest first, the same as the file
+    pub versions: Vec<String>,
+}
+
+impl MavenMetadata {
+    //The file is simple enough that picking out the few tags it has is all there is to it
+    pub fn parse(xml: &str) -> MavenMetadata {
+        MavenMetadata {
+            latest: tag_values(xml, "latest").into_iter().next(),
+            release: tag_values(xml, "release").into_iter().next(),
+            //Some files have a lone <version> outside of <versions> too
+            versions: tag_values(xml, "versions").into_iter()
+                                                 .flat_map(|versions| tag_values(&versions, "version"))
+                                                 .collect(),
+        }
+    }
+}
+
+fn tag_values(xml: &str, tag: &str) -> Vec<String> {
+    let open = format!("<{}>", tag);
+    let close = format!("</{}>", tag);
+    xml.split(&open[..])
+       .skip(1)
+       .filter_map(|part| part.split(&close[..]).next())
+       .map(|value| value.trim().to_string())
+       .collect()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn parses_plain_coordinate() {
+        let coordinate: MavenCoordinate = "net.fabricmc:fabric-loader:0.14.21".parse().unwrap();
+        assert_eq!(coordinate.group, "net.fabricmc");
+        assert_eq!(coordinate.artifact, "fabric-loader");
+        assert_eq!(coordinate.version, "0.14.21");
+        assert_eq!(coordinate.classifier, None);
+        assert_eq!(coordinate.extension, "jar");
+        assert_eq!(coordinate.path(), "net/fabricmc/fabric-loader/0.14.21/fabric-loader-0.14.21.jar");
+        assert_eq!(coordinate.url("https://maven.fabricmc.net/"),
+                   "https://maven.fabricmc.net/net/fabricmc/fabric-loader/0.14.21/fabric-loader-0.14.21.jar");
+    }
+
+    #[test]
+    fn parses_classifier_and_extension() {
+        let coordinate: MavenCoordinate = "de.oceanlabs.mcp:mcp_config:1.20.1-20230612.114412:mappings@txt".parse().unwrap();
+        assert_eq!(coordinate.classifier.as_deref(), Some("mappings"));
+        assert_eq!(coordinate.extension, "txt");
+        assert_eq!(coordinate.path(),
+                   "de/oceanlabs/mcp/mcp_config/1.20.1-20230612.114412/mcp_config-1.20.1-20230612.114412-mappings.txt");
+
+        let natives: MavenCoordinate = "org.lwjgl:lwjgl:3.3.1".parse().unwrap();
+        assert_eq!(natives.with_classifier("natives-linux").path(), "org/lwjgl/lwjgl/3.3.1/lwjgl-3.3.1-natives-linux.jar");
+    }
+
+    #[test]
+    fn display_round_trips() {
+        for name in &["net.minecraftforge:forge:1.20.1-47.1.0",
+                      "net.minecraftforge:forge:1.20.1-47.1.0:universal",
+                      "net.minecraft:client:1.20.1:mappings@txt",
+                      "org.ow2.asm:asm:9.5@zip"] {
+            let coordinate: MavenCoordinate = name.parse().unwrap();
+            assert_eq!(&coordinate.to_string(), name);
+            assert_eq!(coordinate.to_string().parse::<MavenCoordinate>().unwrap(), coordinate);
+        }
+    }
+
+    #[test]
+    fn rejects_bad_names() {
+        for name in &["", "net.fabricmc", "net.fabricmc:fabric-loader", "net.fabricmc::0.14.21",
+                      "a:b:c:d:e", "a:b:c@"] {
+            assert!(name.parse::<MavenCoordinate>().is_err(), "{} parsed", name);
+        }
+    }
+
+    #[test]
+    fn parses_metadata() {
+        let xml = r#"<?xml version="1.0" encoding="UTF-8"?>
+<metadata>
+  <groupId>net.fabricmc</groupId>
+  <artifactId>fabric-loader</artifactId>
+  <version>0.1.0</version>
+  <versioning>
+    <latest>0.15.0</latest>
+    <release>0.14.21</release>
+    <versions>
+      <version>0.14.20</version>
+      <version> 0.14.21 </version>
+      <version>0.15.0</version>
+    </versions>
+    <lastUpdated>20230612114412</lastUpdated>
+  </versioning>
+</metadata>"#;
+        let metadata = MavenMetadata::parse(xml);
+        assert_eq!(metadata.latest.as_deref(), Some("0.15.0"));
+        assert_eq!(metadata.release.as_deref(), Some("0.14.21"));
+        assert_eq!(metadata.versions, vec!["0.14.20", "0.14.21", "0.15.0"]);
+    }
+
+    #[test]
+    fn parses_metadata_without_versions() {
+        let metadata = MavenMetadata::parse("<metadata><versioning></versioning></metadata>");
+        assert_eq!(metadata.latest, None);
+        assert_eq!(metadata.release, None);
+        assert!(metadata.versions.is_empty());
+    }
+}
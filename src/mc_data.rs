@@ -6,9 +6,15 @@ pub struct MojangVersionManifest {
     pub versions: Vec<MojangReleaseProfile>,
 }
 
+pub const VERSION_MANIFEST_URL: &str = "https://launchermeta.mojang.com/mc/game/version_manifest.json";
+
 impl MojangVersionManifest {
+    pub fn fetch() -> Result<Self, reqwest::Error> {
+        reqwest::blocking::get(VERSION_MANIFEST_URL)?.json()
+    }
+
     pub fn look_up_version(&self, version: String) -> Option<&MojangReleaseProfile> {
-        self.versions.iter().filter(|v| v.id == version).next()
+        self.versions.iter().find(|v| v.id == version)
     }
 }
 
@@ -31,6 +37,8 @@ pub struct MojangReleaseProfile {
 
 pub mod mojang_version_data {
     use crate::types::{Or, OrVec};
+    use crate::download::InstallError;
+    use crate::maven::MavenCoordinate;
     use serde::{Deserialize, Serialize};
 
     #[derive(Serialize, Deserialize, Debug)]
@@ -90,6 +98,25 @@ pub mod mojang_version_data {
         pub rules: Option<Vec<Rule>>,
     }
 
+    impl Library {
+        pub fn coordinate(&self) -> Result<MavenCoordinate, InstallError> {
+            self.name.parse()
+        }
+
+        //The classifier holding this library's natives, i.e. natives-linux
+        pub fn natives_classifier(&self, os_name: &str) -> Option<String> {
+            let natives = self.natives.as_ref()?;
+            let classifier = match os_name {
+                "osx" => &natives.osx,
+                "windows" => &natives.windows,
+                "linux" => &natives.linux,
+                _ => &None,
+            };
+            //Old versions had separate 32 and 64 bit natives
+            classifier.as_ref().map(|c| c.replace("${arch}", "64"))
+        }
+    }
+
     #[derive(Serialize, Deserialize, Debug, Clone)]
     pub struct LibraryDownload {
         pub classifiers: Option<Classifiers>,
@@ -1,91 +1,181 @@
+use std::io::Write;
 use std::path::Path;
 use serde::{Serialize, Deserialize};
 use super::super::types::{Or, Or::{First, Second}};
-use super::super::download::{InstallError, try_download_and_write};
+use super::super::download::InstallError;
+use super::super::server::{self, SERVER_JAR};
+use super::super::store;
+use super::super::mc_data::mojang_version_data::Arguments;
+use super::super::mc_data::mojang_version_data::MojangVersionData;
+use super::{LoaderProfile, ProfileLibrary, Side, PROFILE_FILE, profile_timestamp, install_libraries};
+use super::{ModLoader, GameVersion, LoaderBuild, VersionQuery, Versioned};
 
 const VERSION_URL: &str = "https://meta.fabricmc.net/v2/versions";
-pub enum Stability {
-    Stable,
-    Unstable,
-    Both
-}
 
 const MAVEN_URL: &str = "https://maven.fabricmc.net";
+const SERVER_LAUNCH_JAR: &str = "fabric-server-launch.jar";
+
+pub struct Fabric;
+
+impl ModLoader for Fabric {
+    fn name(&self) -> &'static str {
+        "fabric"
+    }
+
+    fn prism_uid(&self) -> &'static str {
+        "net.fabricmc.fabric-loader"
+    }
+
+    fn uses_intermediary(&self) -> bool {
+        true
+    }
+
+    fn game_versions(&self, query: &VersionQuery) -> Result<Vec<GameVersion>, InstallError> {
+        Ok(get_game_versions(query)?.into_iter()
+               .map(|v| GameVersion { version: v.version, stable: v.stable })
+               .collect())
+    }
+
+    fn builds(&self, game_version: &str, query: &VersionQuery) -> Result<Vec<LoaderBuild>, InstallError> {
+        let version = FabricGameVersion { version: String::from(game_version), stable: true };
+        Ok(get_fabric_builds_from_version(&version, query)?.into_iter()
+               .map(|b| LoaderBuild {
+                   loader: String::from(self.name()),
+                   game_version: String::from(game_version),
+                   version: b.loader.version,
+                   build: Some(b.loader.build),
+                   stable: b.loader.stable,
+               })
+               .collect())
+    }
+
+    fn install(&self, build: &LoaderBuild, _vanilla: &MojangVersionData, directory: &Path, side: Side) -> Result<(), InstallError> {
+        let build = get_fabric_build(&build.game_version, &build.version)?;
+        match side {
+            Side::Client => install_fabric_at_instance(build, directory),
+            Side::Server => install_fabric_server_at_instance(build, directory),
+        }
+    }
+
+    fn profile(&self, build: &LoaderBuild, side: Side) -> Result<serde_json::Value, InstallError> {
+        let build = get_fabric_build(&build.game_version, &build.version)?;
+        Ok(serde_json::to_value(build.profile(side))?)
+    }
+}
 
 pub fn install_fabric_at_instance(build: FabricBuild, instance_dir: &Path) -> Result<(), InstallError> {
-    let lib_path = Path::new("./libraries");
-    let libraries = &build.launcher_meta.libraries;
-    let mut libraries: Vec<&FabricLibrary> = libraries.client.iter()
-                    .chain(libraries.common.iter())
-                    .collect();
-    //Add loader and intermediary to libraries
-    let loader = FabricLibrary {
-        name: build.loader.maven.clone(),
-        url: Some(String::from(MAVEN_URL)) 
+    let client = reqwest::blocking::Client::new();
+    let profile = build.profile(Side::Client);
+    install_libraries(&profile.libraries, &client)?;
+
+    let profile = serde_json::to_string_pretty(&profile)?;
+    std::fs::write(instance_dir.join(PROFILE_FILE), profile)?;
+    Ok(())
+}
+
+//Sets up the fabric server launcher on top of a vanilla server installed by
+//server::install_server_to_directory. Libraries are copied into the server directory so that it
+//can be moved around and started on its own.
+pub fn install_fabric_server_at_instance(build: FabricBuild, server_dir: &Path) -> Result<(), InstallError> {
+    let main_class = match build.launcher_meta.server_main_class() {
+        Some(main_class) => main_class.to_string(),
+        None => return Err(InstallError::Unsupported(
+                format!("fabric {} has no server main class", build.loader.version))),
     };
-    let intermediary = FabricLibrary {
-        name: build.intermediary.maven.clone(),
-        url: Some(String::from(MAVEN_URL))
+
+    let client = reqwest::blocking::Client::new();
+    let profile = build.profile(Side::Server);
+    let installed = install_libraries(&profile.libraries, &client)?;
+
+    let lib_path = Path::new("./libraries");
+    let mut classpath = Vec::new();
+    for lib in installed {
+        let relative = Path::new("libraries").join(lib.strip_prefix(lib_path).unwrap_or(&lib));
+        let destination = server_dir.join(&relative);
+        store::link_or_copy(&lib, &destination)?;
+
+        //Class-Path entries are relative URLs, so they always use forward slashes
+        let entry = relative.components()
+                            .map(|c| c.as_os_str().to_string_lossy().into_owned())
+                            .collect::<Vec<String>>()
+                            .join("/");
+        classpath.push(entry);
+    }
+
+    //Fabric moved its launcher into the impl package alongside everything else in 0.12
+    let launcher_class = if main_class.starts_with("net.fabricmc.loader.impl.") {
+        "net.fabricmc.loader.impl.launch.server.FabricServerLauncher"
+    } else {
+        "net.fabricmc.loader.launch.server.FabricServerLauncher"
     };
-    libraries.push(&loader);
-    libraries.push(&intermediary);
-
-    for lib in libraries.iter() {
-        if let FabricLibrary {name, url: Some(url)} = &lib {
-            //library URLs don't include the path, only the domain
-            let mut name = name.split(":");
-            
-            //All library names are split as such:
-            //path.to.lib : unique-lib-name : version-identifier
-            let path = name.next().unwrap();
-            let id = name.next().unwrap();
-            let version = name.next().unwrap();
-
-            //turn path into an actual path
-            let path = path.split(".")
-                           .fold(String::from(""), |state, path| state + path + "/");
-            
-            let url = format!("{}/{}{}/{}", url, path, id, version);
-            let filename = format!("{}-{}", id, version);
-
-            let hash_url = reqwest::Url::parse(&format!("{}/{}.jar.sha1", url, filename))?;
-            let hash = reqwest::blocking::get(hash_url)?
-                                         .text()?;
-            
-            let lib_location = lib_path.join(path).join(id).join(version);
-            let should_download = match std::fs::read(&lib_location.join(format!("{}.jar", filename))) {
-                Err(_) => true,
-                Ok(b) => sha1::Sha1::from(b).digest().to_string() != hash
-            };
-
-            if should_download {
-                let jar_url = &format!("{}/{}.jar", url, filename);
-                try_download_and_write(jar_url, &*lib_location, &format!("{}.jar", filename), None)?;
-                println!("Installed {}", filename);
+    write_server_launch_jar(&server_dir.join(SERVER_LAUNCH_JAR), launcher_class, &main_class, &classpath)?;
+
+    //Read by the launcher to find the vanilla jar it's supposed to load
+    std::fs::write(server_dir.join("fabric-server-launch.properties"),
+                   format!("serverJar={}\n", SERVER_JAR))?;
+
+    server::write_start_scripts(server_dir, &[
+        String::from("-jar"),
+        String::from(SERVER_LAUNCH_JAR),
+    ])?;
+
+    let profile = serde_json::to_string_pretty(&profile)?;
+    std::fs::write(server_dir.join(PROFILE_FILE), profile)?;
+    Ok(())
+}
+
+//Equivalent to the jar produced by the official installer: a manifest pointing at the launcher
+//and the libraries, plus the embedded properties telling the launcher which class comes next
+fn write_server_launch_jar(path: &Path, launcher_class: &str, main_class: &str, classpath: &[String]) -> Result<(), InstallError> {
+    let manifest = format!("Manifest-Version: 1.0\r\nMain-Class: {}\r\nClass-Path: {}\r\n\r\n",
+                           launcher_class,
+                           classpath.join(" "));
+
+    let mut jar = zip::ZipWriter::new(std::fs::File::create(path)?);
+    let options = zip::write::FileOptions::default();
+    jar.start_file("META-INF/MANIFEST.MF", options)?;
+    jar.write_all(wrap_manifest(&manifest).as_bytes())?;
+    jar.start_file("fabric-server-launch.properties", options)?;
+    jar.write_all(format!("launch.mainClass={}\n", main_class).as_bytes())?;
+    jar.finish()?;
+    Ok(())
+}
+
+//Manifest lines can't be longer than 72 bytes; longer ones continue on the next line after a space
+fn wrap_manifest(manifest: &str) -> String {
+    let mut wrapped = String::new();
+    for line in manifest.split("\r\n") {
+        let mut line = line;
+        let mut width = 72;
+        while line.len() > width {
+            let mut split = width;
+            while !line.is_char_boundary(split) {
+                split -= 1;
             }
+            wrapped.push_str(&line[..split]);
+            wrapped.push_str("\r\n ");
+            line = &line[split..];
+            //continuation lines lose a byte to the leading space
+            width = 71;
         }
+        wrapped.push_str(line);
+        wrapped.push_str("\r\n");
     }
+    //split leaves an extra empty line at the end
+    wrapped.truncate(wrapped.len() - 2);
+    wrapped
+}
 
-    let launcher_data = serde_json::to_string_pretty(&build)?;
-
-    std::fs::write(&instance_dir.with_file_name("fabric_info.json"), launcher_data)?;
-    Ok(())
+pub fn get_fabric_build(game_version: &str, loader_version: &str) -> Result<FabricBuild, reqwest::Error> {
+    reqwest::blocking::get(reqwest::Url::parse(&format!("{}/loader/{}/{}", VERSION_URL, game_version, loader_version)).unwrap())?
+        .error_for_status()?
+        .json()
 }
 
-pub fn get_fabric_builds_from_version(version: &FabricGameVersion, stability: Stability ) -> Result<Vec<FabricBuild>, reqwest::Error> {
-    let result: Vec<FabricBuild> = reqwest::blocking::get(reqwest::Url::parse(&format!("{}/loader/{}", VERSION_URL, version.version)).unwrap())?.json()?; 
-    let result = if let Stability::Stable = stability {
-        result.into_iter()
-              .filter(|ver| ver.loader.stable)
-              .map(|ver| ver)
-              .collect::<Vec<FabricBuild>>()
-    } else if let Stability::Unstable = stability {
-        result.into_iter()
-              .filter(|ver| !ver.loader.stable)
-              .map(|ver| ver)
-              .collect::<Vec<FabricBuild>>()
-    } else { result };
-    Ok(result)
+//Builds come back sorted by the query rather than in whatever order the API uses
+pub fn get_fabric_builds_from_version(version: &FabricGameVersion, query: &VersionQuery) -> Result<Vec<FabricBuild>, reqwest::Error> {
+    let result: Vec<FabricBuild> = reqwest::blocking::get(reqwest::Url::parse(&format!("{}/loader/{}", VERSION_URL, version.version)).unwrap())?.error_for_status()?.json()?;
+    Ok(query.apply(result))
 }
 
 #[derive(Serialize, Deserialize, Debug)]
@@ -96,6 +186,69 @@ pub struct FabricBuild {
     pub launcher_meta: FabricBuildMeta
 }
 
+impl Versioned for FabricBuild {
+    fn version(&self) -> &str {
+        &self.loader.version
+    }
+
+    fn build(&self) -> Option<u32> {
+        Some(self.loader.build)
+    }
+
+    fn stable(&self) -> bool {
+        self.loader.stable
+    }
+}
+
+impl FabricBuild {
+    //The same profile meta.fabricmc.net serves from /loader/<game>/<loader>/profile/json
+    pub fn profile(&self, side: Side) -> LoaderProfile {
+        //Intermediary mappings are versioned after the game version they map
+        let game_version = &self.intermediary.version;
+        let libraries = &self.launcher_meta.libraries;
+        let side_libraries = match side {
+            Side::Client => &libraries.client,
+            Side::Server => &libraries.server,
+        };
+
+        let mut profile_libraries: Vec<ProfileLibrary> = libraries.common.iter()
+                    .chain(side_libraries.iter())
+                    .map(|lib| ProfileLibrary { name: lib.name.clone(), url: lib.url.clone() })
+                    .collect();
+        profile_libraries.push(ProfileLibrary {
+            name: self.intermediary.maven.clone(),
+            url: Some(format!("{}/", MAVEN_URL)),
+        });
+        profile_libraries.push(ProfileLibrary {
+            name: self.loader.maven.clone(),
+            url: Some(format!("{}/", MAVEN_URL)),
+        });
+
+        let main_class = match side {
+            Side::Client => self.launcher_meta.client_main_class(),
+            Side::Server => self.launcher_meta.server_main_class().unwrap_or_default(),
+        };
+
+        //Lets mods that check for the vanilla main class on the command line find it
+        let jvm = match side {
+            Side::Client => vec![First(String::from("-DFabricMcEmu= net.minecraft.client.main.Main "))],
+            Side::Server => Vec::new(),
+        };
+
+        let time = profile_timestamp();
+        LoaderProfile {
+            id: format!("fabric-loader-{}-{}", self.loader.version, game_version),
+            inherits_from: game_version.clone(),
+            release_time: time.clone(),
+            time,
+            release_type: String::from("release"),
+            main_class: main_class.to_string(),
+            arguments: Arguments { game: Vec::new(), jvm },
+            libraries: profile_libraries,
+        }
+    }
+}
+
 #[derive(Serialize, Deserialize, Debug)]
 pub struct FabricBuildLoader {
     pub separator: String,
@@ -120,6 +273,23 @@ pub struct FabricBuildMeta {
     main_class: Or<FabricMainClass, String>
 }
 
+impl FabricBuildMeta {
+    pub fn client_main_class(&self) -> &str {
+        match &self.main_class {
+            First(main_class) => &main_class.client,
+            Second(main_class) => main_class,
+        }
+    }
+
+    //Older loader builds only publish a single client main class
+    pub fn server_main_class(&self) -> Option<&str> {
+        match &self.main_class {
+            First(main_class) => Some(&main_class.server),
+            Second(_) => None,
+        }
+    }
+}
+
 #[derive(Serialize, Deserialize, Debug)]
 pub struct FabricMainClass {
     client: String,
@@ -139,20 +309,9 @@ pub struct FabricLibrary {
     url: Option<String>
 }
 
-pub fn get_game_versions(stability: Stability) -> Result<Vec<FabricGameVersion>, reqwest::Error> {
-    let result: Vec<FabricGameVersion> = reqwest::blocking::get(reqwest::Url::parse(&format!("{}/game", VERSION_URL)).unwrap())?.json()?;
-    let result = if let Stability::Stable = stability {
-        result.into_iter()
-              .filter(|ver| ver.stable)
-              .map(|ver| ver)
-              .collect::<Vec<FabricGameVersion>>()
-    } else if let Stability::Unstable = stability {
-        result.into_iter()
-              .filter(|ver| !ver.stable)
-              .map(|ver| ver)
-              .collect::<Vec<FabricGameVersion>>()
-    } else { result };
-    Ok(result)
+pub fn get_game_versions(query: &VersionQuery) -> Result<Vec<FabricGameVersion>, reqwest::Error> {
+    let result: Vec<FabricGameVersion> = reqwest::blocking::get(reqwest::Url::parse(&format!("{}/game", VERSION_URL)).unwrap())?.error_for_status()?.json()?;
+    Ok(query.filter(result))
 }
 
 #[derive(Serialize, Deserialize, Debug)]
@@ -160,3 +319,17 @@ pub struct FabricGameVersion {
     pub version: String,
     pub stable: bool
 }
+
+impl Versioned for FabricGameVersion {
+    fn version(&self) -> &str {
+        &self.version
+    }
+
+    fn build(&self) -> Option<u32> {
+        None
+    }
+
+    fn stable(&self) -> bool {
+        self.stable
+    }
+}
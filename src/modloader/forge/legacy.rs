@@ -0,0 +1,204 @@
+//Forge before 1.13 had nothing to run at install time. The installer just carries the universal
+//jar and a version json that puts it on the classpath with a tweaker in minecraftArguments.
+use std::path::{Path, PathBuf};
+use std::process::Command;
+use serde::{Serialize, Deserialize};
+use rayon::prelude::*;
+use super::super::super::download::{InstallError, download_and_check};
+use super::super::super::mc_data::mojang_version_data::MojangVersionData;
+use super::super::super::server::{self, SERVER_JAR};
+use super::super::super::store;
+use super::super::{Side, PROFILE_FILE};
+use super::super::super::modpack::instance_path;
+use super::{Installer, artifact_path, library_path, read_entry};
+
+const MOJANG_LIBRARIES_URL: &str = "https://libraries.minecraft.net/";
+const FORGE_MAVEN_URL: &str = "https://maven.minecraftforge.net/";
+//Old version jsons point at hosts that have since moved to the maven above
+const OLD_FORGE_MAVENS: &[&str] = &[
+    "http://files.minecraftforge.net/maven/",
+    "https://files.minecraftforge.net/maven/",
+];
+
+pub(super) fn install_legacy(mut installer: Installer, profile_json: &[u8], vanilla: &MojangVersionData, directory: &Path, side: Side) -> Result<(), InstallError> {
+    let profile: LegacyInstallProfile = serde_json::from_slice(profile_json)?;
+
+    if profile.install.minecraft != vanilla.id {
+        return Err(InstallError::Unsupported(
+                format!("{} is for minecraft {}, not {}", profile.install.version, profile.install.minecraft, vanilla.id)));
+    }
+
+    //Servers keep their libraries next to them so the directory runs on its own
+    let lib_dir = match side {
+        Side::Client => PathBuf::from("./libraries"),
+        Side::Server => directory.join("libraries"),
+    };
+    std::fs::create_dir_all(&lib_dir)?;
+
+    //The universal jar comes out of the installer instead of off the maven
+    let universal = read_entry(&mut installer, &profile.install.file_path)?;
+    let universal_path = library_path(&lib_dir, &profile.install.path)?;
+    store::place(&universal, &universal_path)?;
+
+    //The launcher fetches every library for the client, while servers only get what's marked for
+    //them
+    let libraries: Vec<&LegacyLibrary> = profile.version_info.libraries.iter()
+                                                .filter(|lib| lib.name != profile.install.path)
+                                                .filter(|lib| side == Side::Client || lib.serverreq.unwrap_or(false))
+                                                .collect();
+    let client = reqwest::blocking::Client::new();
+    libraries.par_iter()
+             .map(|lib| install_legacy_library(lib, &lib_dir, &client))
+             .collect::<Result<Vec<()>, InstallError>>()?;
+
+    if side == Side::Server {
+        //The universal jar's manifest expects the vanilla server next to it under its old name
+        store::link_or_copy(&directory.join(SERVER_JAR), &directory.join(format!("minecraft_server.{}.jar", vanilla.id)))?;
+        store::place(&universal, &instance_path(directory, &profile.install.file_path)?)?;
+        server::write_start_scripts(directory, &[String::from("-jar"), profile.install.file_path.clone()])?;
+    }
+
+    let version_info = serde_json::to_string_pretty(&profile.version_info)?;
+    std::fs::write(directory.join(PROFILE_FILE), version_info)?;
+    Ok(())
+}
+
+fn install_legacy_library(lib: &LegacyLibrary, lib_dir: &Path, client: &reqwest::blocking::Client) -> Result<(), InstallError> {
+    let path = artifact_path(&lib.name)?;
+    let location = instance_path(lib_dir, &path)?;
+    if let Ok(bytes) = std::fs::read(&location) {
+        if lib.matches_checksums(&bytes) {
+            return Ok(());
+        }
+    }
+
+    let repository = match &lib.url {
+        Some(url) if OLD_FORGE_MAVENS.contains(&url.as_str()) => FORGE_MAVEN_URL,
+        Some(url) => url.as_str(),
+        None => MOJANG_LIBRARIES_URL,
+    };
+    let url = format!("{}/{}", repository.trim_end_matches('/'), path);
+
+    //Forge only kept some of its libraries around as pack200 archives. Unpacking doesn't give back
+    //the exact same jar, so the checksums only apply to plain downloads.
+    let bytes = match download_and_check(&url, Some(client)) {
+        Ok(bytes) if lib.matches_checksums(&bytes) => bytes,
+        Ok(_) => return Err(InstallError::HashError(format!("{} doesn't match any of its checksums", url))),
+        Err(InstallError::WebError(e)) if e.status().is_some() && lib.url.is_some() => {
+            let packed = download_and_check(&format!("{}.pack.xz", url), Some(client))?;
+            unpack_pack_xz(&packed, &location)?;
+            println!("Installed {}", lib.name);
+            return Ok(());
+        }
+        Err(e) => return Err(e),
+    };
+
+    store::place(&bytes, &location)?;
+    println!("Installed {}", lib.name);
+    Ok(())
+}
+
+//.pack.xz files are xz compressed pack200 archives with forge's checksums tacked onto the end:
+//[pack200 data][checksums][checksums length as a little endian u32]["SIGN"]
+fn unpack_pack_xz(packed: &[u8], jar: &Path) -> Result<(), InstallError> {
+    let mut decompressed = Vec::new();
+    lzma_rs::xz_decompress(&mut std::io::BufReader::new(packed), &mut decompressed)
+        .map_err(|e| InstallError::Unsupported(format!("couldn't decompress {}: {:?}", jar.display(), e)))?;
+
+    let len = decompressed.len();
+    if len < 8 || &decompressed[len - 4..] != b"SIGN" {
+        return Err(InstallError::Unsupported(format!("{} has no signature", jar.display())));
+    }
+    let mut checksums_len = [0; 4];
+    checksums_len.copy_from_slice(&decompressed[len - 8..len - 4]);
+    let checksums_len = u32::from_le_bytes(checksums_len) as usize;
+    if checksums_len + 8 > len {
+        return Err(InstallError::Unsupported(format!("{} has a broken signature", jar.display())));
+    }
+
+    let pack = jar.with_extension("jar.pack");
+    if let Some(parent) = pack.parent() {
+        std::fs::create_dir_all(parent)?;
+    }
+    std::fs::write(&pack, &decompressed[..len - checksums_len - 8])?;
+    store::unlink(jar)?;
+
+    //Pack200 went away in java 14, so this needs an older JDK's unpack200 around
+    let status = Command::new(unpack200_command())
+                         .arg(&pack)
+                         .arg(jar)
+                         .status();
+    std::fs::remove_file(&pack)?;
+    match status {
+        Ok(status) if status.success() => Ok(()),
+        Ok(status) => Err(InstallError::Unsupported(format!("unpack200 failed on {} with {}", jar.display(), status))),
+        Err(e) => Err(InstallError::Unsupported(format!("{} needs unpack200 from java 13 or older: {}", jar.display(), e))),
+    }
+}
+
+fn unpack200_command() -> PathBuf {
+    match std::env::var_os("JAVA_HOME") {
+        Some(home) => Path::new(&home).join("bin").join("unpack200"),
+        None => PathBuf::from("unpack200"),
+    }
+}
+
+#[derive(Serialize, Deserialize, Debug)]
+pub struct LegacyInstallProfile {
+    pub install: LegacyInstall,
+    #[serde(rename = "versionInfo")]
+    pub version_info: LegacyVersionInfo,
+}
+
+#[derive(Serialize, Deserialize, Debug)]
+pub struct LegacyInstall {
+    pub path: String,
+    pub version: String,
+    pub minecraft: String,
+    #[serde(rename = "filePath")]
+    pub file_path: String,
+}
+
+//Kept whole so it can be written back out as the instance's profile
+#[derive(Serialize, Deserialize, Debug)]
+pub struct LegacyVersionInfo {
+    pub id: String,
+    #[serde(rename = "inheritsFrom")]
+    pub inherits_from: Option<String>,
+    #[serde(rename = "minecraftArguments")]
+    pub minecraft_arguments: String,
+    #[serde(rename = "mainClass")]
+    pub main_class: String,
+    pub libraries: Vec<LegacyLibrary>,
+    #[serde(flatten)]
+    pub extra: serde_json::Map<String, serde_json::Value>,
+}
+
+#[derive(Serialize, Deserialize, Debug)]
+pub struct LegacyLibrary {
+    pub name: String,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub url: Option<String>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub checksums: Option<Vec<String>>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub clientreq: Option<bool>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub serverreq: Option<bool>,
+    //natives, extract, rules and the like, which only need passing through
+    #[serde(flatten)]
+    pub extra: serde_json::Map<String, serde_json::Value>,
+}
+
+impl LegacyLibrary {
+    //Libraries without checksums are taken as they are
+    fn matches_checksums(&self, bytes: &[u8]) -> bool {
+        match &self.checksums {
+            Some(checksums) if !checksums.is_empty() => {
+                let sha1 = sha1::Sha1::from(bytes).digest().to_string();
+                checksums.contains(&sha1)
+            }
+            _ => true,
+        }
+    }
+}
@@ -0,0 +1,566 @@
+use std::collections::HashMap;
+use std::io::{Cursor, Read};
+use std::path::{Path, PathBuf};
+use std::process::Command;
+use serde::{Serialize, Deserialize};
+use rayon::prelude::*;
+use super::super::download::{InstallError, download_and_check, try_download_verified};
+use super::super::instance::InstanceManifest;
+use super::super::mc_data::mojang_version_data::MojangVersionData;
+use super::super::server::{self, SERVER_JAR};
+use super::super::maven::MavenCoordinate;
+use super::super::modpack::instance_path;
+use super::super::store;
+use super::{Side, PROFILE_FILE, ModLoader, GameVersion, LoaderBuild, VersionQuery};
+
+mod legacy;
+
+const MAVEN_URL: &str = "https://maven.minecraftforge.net";
+const PROMOTIONS_URL: &str = "https://files.minecraftforge.net/net/minecraftforge/forge/promotions_slim.json";
+
+type Installer = zip::ZipArchive<Cursor<Vec<u8>>>;
+
+#[derive(Serialize, Deserialize, Debug, Clone)]
+pub struct ForgeVersion {
+    //The version of minecraft this build is for, i.e. 1.16.5
+    pub minecraft: String,
+    //The forge build on its own, i.e. 36.2.39
+    pub forge: String,
+    //What the maven calls it, i.e. 1.16.5-36.2.39
+    pub id: String,
+}
+
+impl ForgeVersion {
+    pub fn installer_url(&self) -> String {
+        format!("{}/net/minecraftforge/forge/{}/forge-{}-installer.jar", MAVEN_URL, self.id, self.id)
+    }
+}
+
+//Lists every forge build for a minecraft version, newest first
+pub fn get_forge_versions(mc_version: &str) -> Result<Vec<ForgeVersion>, InstallError> {
+    Ok(get_all_forge_versions()?.into_iter()
+           .filter(|version| version.minecraft == mc_version)
+           .collect())
+}
+
+//Lists every forge build there is, newest first
+pub fn get_all_forge_versions() -> Result<Vec<ForgeVersion>, InstallError> {
+    let client = reqwest::blocking::Client::new();
+    let forge: MavenCoordinate = "net.minecraftforge:forge:0".parse()?;
+    let metadata = forge.metadata(MAVEN_URL, &client)?;
+
+    let mut versions: Vec<ForgeVersion> = metadata.versions.into_iter()
+        .filter_map(|id| {
+            //Some old builds have the minecraft version tacked onto the end too, i.e.
+            //1.7.10-10.13.4.1614-1.7.10
+            let mut parts = id.splitn(2, '-');
+            let minecraft = parts.next()?.to_string();
+            let forge = parts.next()?.split('-').next()?.to_string();
+            Some(ForgeVersion { minecraft, forge, id })
+        })
+        .collect();
+    versions.reverse();
+    Ok(versions)
+}
+
+//Forge's picks for each minecraft version, keyed like "1.16.5-recommended" or "1.16.5-latest"
+pub fn get_promotions() -> Result<HashMap<String, String>, reqwest::Error> {
+    let promotions: ForgePromotions = reqwest::blocking::get(PROMOTIONS_URL)?.json()?;
+    Ok(promotions.promos)
+}
+
+#[derive(Serialize, Deserialize, Debug)]
+struct ForgePromotions {
+    promos: HashMap<String, String>,
+}
+
+pub struct Forge;
+
+impl Forge {
+    fn forge_version(&self, build: &LoaderBuild) -> Result<ForgeVersion, InstallError> {
+        get_forge_versions(&build.game_version)?.into_iter()
+            .find(|version| version.forge == build.version)
+            .ok_or_else(|| InstallError::Unsupported(format!("no forge build {} for {}", build.version, build.game_version)))
+    }
+}
+
+impl ModLoader for Forge {
+    fn name(&self) -> &'static str {
+        "forge"
+    }
+
+    fn prism_uid(&self) -> &'static str {
+        "net.minecraftforge"
+    }
+
+    fn game_versions(&self, query: &VersionQuery) -> Result<Vec<GameVersion>, InstallError> {
+        let mut versions: Vec<GameVersion> = Vec::new();
+        for version in get_all_forge_versions()? {
+            if !versions.iter().any(|v| v.version == version.minecraft) {
+                versions.push(GameVersion { version: version.minecraft, stable: true });
+            }
+        }
+        Ok(query.filter(versions))
+    }
+
+    //Only the build forge recommends counts as stable
+    fn builds(&self, game_version: &str, query: &VersionQuery) -> Result<Vec<LoaderBuild>, InstallError> {
+        let promotions = get_promotions()?;
+        let recommended = promotions.get(&format!("{}-recommended", game_version));
+        let builds = get_forge_versions(game_version)?.into_iter()
+               .map(|v| LoaderBuild {
+                   loader: String::from(self.name()),
+                   game_version: String::from(game_version),
+                   stable: Some(&v.forge) == recommended,
+                   version: v.forge,
+                   build: None,
+               })
+               .collect();
+        Ok(query.apply(builds))
+    }
+
+    //The installer's processors have to run before the launcher can use the profile
+    fn launcher_ready(&self) -> bool {
+        false
+    }
+
+    fn install(&self, build: &LoaderBuild, vanilla: &MojangVersionData, directory: &Path, side: Side) -> Result<(), InstallError> {
+        let version = self.forge_version(build)?;
+        match side {
+            Side::Client => install_forge_at_instance(&version, vanilla, directory),
+            Side::Server => install_forge_server_at_instance(&version, vanilla, directory),
+        }
+    }
+
+    fn profile(&self, build: &LoaderBuild, _side: Side) -> Result<serde_json::Value, InstallError> {
+        let version = self.forge_version(build)?;
+        installer_profile(download_installer(&version.installer_url())?)
+    }
+}
+
+pub fn install_forge_at_instance(version: &ForgeVersion, vanilla: &MojangVersionData, instance_dir: &Path) -> Result<(), InstallError> {
+    let installer = download_installer(&version.installer_url())?;
+    install_from_installer(installer, vanilla, instance_dir, Side::Client)
+}
+
+//Installs on top of a vanilla server installed by server::install_server_to_directory
+pub fn install_forge_server_at_instance(version: &ForgeVersion, vanilla: &MojangVersionData, server_dir: &Path) -> Result<(), InstallError> {
+    let installer = download_installer(&version.installer_url())?;
+    install_from_installer(installer, vanilla, server_dir, Side::Server)
+}
+
+pub(crate) fn download_installer(url: &String) -> Result<Vec<u8>, InstallError> {
+    let client = reqwest::blocking::Client::new();
+    let bytes = download_and_check(url, Some(&client))?;
+
+    //The maven publishes hashes next to every file
+    let sha1 = client.get(&format!("{}.sha1", url)).send()?;
+    if sha1.status().is_success() {
+        let sha1 = sha1.text()?;
+        let sha1 = sha1.split_whitespace().next().unwrap_or("");
+        let actual = sha1::Sha1::from(&bytes).digest().to_string();
+        if actual != sha1 {
+            return Err(InstallError::HashError(
+                    format!("{} has hash {}, expected {}", url, actual, sha1)));
+        }
+    }
+
+    Ok(bytes)
+}
+
+//The version json an installer leaves behind for launchers, without installing anything
+pub(crate) fn installer_profile(installer_bytes: Vec<u8>) -> Result<serde_json::Value, InstallError> {
+    let mut installer = zip::ZipArchive::new(Cursor::new(installer_bytes))?;
+    let profile: serde_json::Value = serde_json::from_slice(&read_entry(&mut installer, "install_profile.json")?)?;
+
+    //Legacy installers keep it inside the install profile
+    if let Some(version_info) = profile.get("versionInfo") {
+        return Ok(version_info.clone());
+    }
+    let json = profile.get("json")
+                      .and_then(|json| json.as_str())
+                      .unwrap_or("/version.json");
+    Ok(serde_json::from_slice(&read_entry(&mut installer, json.trim_start_matches('/'))?)?)
+}
+
+//Does what the forge installer does when you click through it: install libraries, run the
+//processors that patch minecraft, then leave a version profile behind for launching
+pub(crate) fn install_from_installer(installer_bytes: Vec<u8>, vanilla: &MojangVersionData, directory: &Path, side: Side) -> Result<(), InstallError> {
+    let mut installer = zip::ZipArchive::new(Cursor::new(installer_bytes.clone()))?;
+    let profile_json = read_entry(&mut installer, "install_profile.json")?;
+
+    //Before 1.13 the install profile wrapped the version json, and there was nothing to run
+    if serde_json::from_slice::<serde_json::Value>(&profile_json)?.get("install").is_some() {
+        return legacy::install_legacy(installer, &profile_json, vanilla, directory, side);
+    }
+
+    let profile: InstallProfile = serde_json::from_slice(&profile_json)?;
+    let version_json = read_entry(&mut installer, profile.json.trim_start_matches('/'))?;
+    let version_info: VersionInfo = serde_json::from_slice(&version_json)?;
+
+    if profile.minecraft != vanilla.id {
+        return Err(InstallError::Unsupported(
+                format!("{} is for minecraft {}, not {}", profile.version, profile.minecraft, vanilla.id)));
+    }
+
+    //Servers keep their libraries next to them so the directory runs on its own
+    let lib_dir = match side {
+        Side::Client => PathBuf::from("./libraries"),
+        Side::Server => directory.join("libraries"),
+    };
+    std::fs::create_dir_all(&lib_dir)?;
+    let lib_dir = lib_dir.canonicalize()?;
+    let directory = directory.canonicalize()?;
+
+    let libraries: Vec<&ForgeLibrary> = profile.libraries.iter()
+                                               .chain(version_info.libraries.iter())
+                                               .collect();
+    install_libraries(&libraries, &mut installer, &lib_dir)?;
+
+    let minecraft_jar = match side {
+        Side::Client => directory.join("client.jar"),
+        Side::Server => directory.join(SERVER_JAR),
+    };
+
+    //Newer servers look for the vanilla jar inside the libraries, older ones next to the forge jar
+    if side == Side::Server {
+        let server_jar = match &profile.server_jar_path {
+            Some(path) => {
+                let path = path.strip_prefix("{LIBRARY_DIR}/").ok_or_else(|| {
+                    InstallError::Unsupported(format!("{} wants the server jar outside of the libraries", profile.version))
+                })?;
+                instance_path(&lib_dir, &path.replace("{MINECRAFT_VERSION}", &vanilla.id))?
+            }
+            None => directory.join(format!("minecraft_server.{}.jar", vanilla.id)),
+        };
+        store::link_or_copy(&minecraft_jar, &server_jar)?;
+    }
+
+    let tmp_dir = TempDir::create(directory.join(".forge-installer"))?;
+
+    let mut data = HashMap::new();
+    for (key, value) in profile.data.iter() {
+        let value = match side {
+            Side::Client => &value.client,
+            Side::Server => &value.server,
+        };
+        data.insert(key.clone(), resolve_data(value, &mut installer, &tmp_dir.0, &lib_dir)?);
+    }
+    let path_string = |path: &Path| path.to_string_lossy().into_owned();
+    data.insert(String::from("SIDE"), String::from(side.name()));
+    data.insert(String::from("MINECRAFT_JAR"), path_string(&minecraft_jar));
+    data.insert(String::from("MINECRAFT_VERSION"), vanilla.id.clone());
+    data.insert(String::from("ROOT"), path_string(&directory));
+    data.insert(String::from("LIBRARY_DIR"), path_string(&lib_dir));
+    let installer_path = tmp_dir.0.join("installer.jar");
+    std::fs::write(&installer_path, installer_bytes)?;
+    data.insert(String::from("INSTALLER"), path_string(&installer_path));
+
+    let processors = profile.processors.iter()
+                            .filter(|processor| match &processor.sides {
+                                Some(sides) => sides.iter().any(|s| s == side.name()),
+                                None => true,
+                            });
+    //Processors run on the java the instance is set up with, if it has one
+    let java = java_command(InstanceManifest::load(&directory).ok().and_then(|manifest| manifest.java.path));
+    for processor in processors {
+        run_processor(processor, &data, &lib_dir, &java)?;
+    }
+    drop(tmp_dir);
+
+    if side == Side::Server {
+        write_server_scripts(&profile, &mut installer, &directory, &lib_dir)?;
+    }
+
+    //version.json is already the profile launchers expect
+    std::fs::write(directory.join(PROFILE_FILE), version_json)?;
+    Ok(())
+}
+
+//Where the installer's files are unpacked for its processors. It's removed however the install
+//ends, so a failed processor doesn't leave it in the instance.
+struct TempDir(PathBuf);
+
+impl TempDir {
+    fn create(path: PathBuf) -> Result<Self, InstallError> {
+        std::fs::create_dir_all(&path)?;
+        Ok(TempDir(path))
+    }
+}
+
+impl Drop for TempDir {
+    fn drop(&mut self) {
+        let _ = std::fs::remove_dir_all(&self.0);
+    }
+}
+
+fn install_libraries(libraries: &[&ForgeLibrary], installer: &mut Installer, lib_dir: &Path) -> Result<(), InstallError> {
+    let client = reqwest::blocking::Client::new();
+    let artifacts: Vec<&ForgeArtifact> = libraries.iter()
+                                                  .filter_map(|lib| lib.downloads.as_ref())
+                                                  .filter_map(|downloads| downloads.artifact.as_ref())
+                                                  .collect();
+
+    //Artifacts without a url are shipped inside the installer under maven/
+    let (bundled, remote): (Vec<&ForgeArtifact>, Vec<&ForgeArtifact>) = artifacts.into_iter()
+                                                                                 .partition(|a| a.url.is_empty());
+    for artifact in bundled {
+        let bytes = read_entry(installer, &format!("maven/{}", artifact.path))?;
+        store::place(&bytes, &instance_path(lib_dir, &artifact.path)?)?;
+    }
+
+    remote.par_iter()
+          .map(|artifact| {
+              let location = instance_path(lib_dir, &artifact.path)?;
+              let dir = location.parent().unwrap_or(lib_dir);
+              let name = location.file_name()
+                                 .map(|name| name.to_string_lossy().into_owned())
+                                 .unwrap_or_default();
+              match &artifact.sha1 {
+                  Some(sha1) => try_download_verified(&artifact.url, sha1, dir, &name, Some(&client)),
+                  None if location.exists() => Ok(()),
+                  None => super::super::download::try_download_and_write(&artifact.url, dir, &name, Some(&client)),
+              }
+          })
+          .collect()
+}
+
+//Data values are either [maven:coordinates] pointing into the libraries, 'literals', or
+//paths to files inside the installer
+fn resolve_data(value: &str, installer: &mut Installer, tmp_dir: &Path, lib_dir: &Path) -> Result<String, InstallError> {
+    if value.starts_with('[') && value.ends_with(']') {
+        Ok(library_path(lib_dir, &value[1..value.len() - 1])?.to_string_lossy().into_owned())
+    } else if value.starts_with('\'') && value.ends_with('\'') && value.len() > 1 {
+        Ok(value[1..value.len() - 1].to_string())
+    } else {
+        let entry = value.trim_start_matches('/');
+        let bytes = read_entry(installer, entry)?;
+        let path = instance_path(tmp_dir, entry)?;
+        if let Some(parent) = path.parent() {
+            std::fs::create_dir_all(parent)?;
+        }
+        std::fs::write(&path, bytes)?;
+        Ok(path.to_string_lossy().into_owned())
+    }
+}
+
+fn run_processor(processor: &Processor, data: &HashMap<String, String>, lib_dir: &Path, java: &Path) -> Result<(), InstallError> {
+    let outputs = processor.outputs.iter()
+                           .map(|(file, sha1)| Ok((resolve_arg(file, data, lib_dir)?, resolve_arg(sha1, data, lib_dir)?)))
+                           .collect::<Result<Vec<(String, String)>, InstallError>>()?;
+
+    //Like the real installer, skip processors whose outputs are already there
+    let outputs_valid = || outputs.iter().all(|(file, sha1)| match std::fs::read(file) {
+        Err(_) => false,
+        Ok(b) => &sha1::Sha1::from(b).digest().to_string() == sha1,
+    });
+    if !outputs.is_empty() && outputs_valid() {
+        return Ok(());
+    }
+
+    let jar = library_path(lib_dir, &processor.jar)?;
+    let main_class = jar_main_class(&jar)?;
+
+    let mut classpath = vec![jar];
+    for lib in processor.classpath.iter() {
+        classpath.push(library_path(lib_dir, lib)?);
+    }
+    let classpath = std::env::join_paths(classpath)
+                        .map_err(|e| InstallError::Unsupported(e.to_string()))?;
+
+    let args = processor.args.iter()
+                        .map(|arg| resolve_arg(arg, data, lib_dir))
+                        .collect::<Result<Vec<String>, InstallError>>()?;
+
+    println!("Running {}", processor.jar);
+    let status = Command::new(java)
+                         .arg("-cp")
+                         .arg(classpath)
+                         .arg(&main_class)
+                         .args(&args)
+                         .status()?;
+    if !status.success() {
+        return Err(InstallError::Unsupported(format!("processor {} failed with {}", processor.jar, status)));
+    }
+
+    for (file, sha1) in outputs.iter() {
+        let actual = sha1::Sha1::from(std::fs::read(file)?).digest().to_string();
+        if &actual != sha1 {
+            std::fs::remove_file(file)?;
+            return Err(InstallError::HashError(
+                    format!("{} produced {} with hash {}, expected {}", processor.jar, file, actual, sha1)));
+        }
+    }
+    Ok(())
+}
+
+//Arguments are either [maven:coordinates] or text with {DATA} entries substituted in
+fn resolve_arg(arg: &str, data: &HashMap<String, String>, lib_dir: &Path) -> Result<String, InstallError> {
+    if arg.starts_with('[') && arg.ends_with(']') {
+        return Ok(library_path(lib_dir, &arg[1..arg.len() - 1])?.to_string_lossy().into_owned());
+    }
+
+    let mut resolved = String::new();
+    let mut rest = arg;
+    while let Some(start) = rest.find('{') {
+        let end = match rest[start..].find('}') {
+            Some(end) => start + end,
+            None => break,
+        };
+        let key = &rest[start + 1..end];
+        let value = data.get(key).ok_or_else(|| {
+            InstallError::Unsupported(format!("processor argument {} uses missing data {}", arg, key))
+        })?;
+        resolved.push_str(&rest[..start]);
+        resolved.push_str(value);
+        rest = &rest[end + 1..];
+    }
+    resolved.push_str(rest);
+    Ok(resolved)
+}
+
+//Newer servers start from argument files shipped in the installer, older ones from the forge jar
+fn write_server_scripts(profile: &InstallProfile, installer: &mut Installer, directory: &Path, lib_dir: &Path) -> Result<(), InstallError> {
+    if installer.by_name("data/unix_args.txt").is_ok() {
+        //The version ends up as a folder name, so it gets the same check as a path
+        instance_path(lib_dir, &profile.version)?;
+        let args_dir = Path::new("libraries").join(loader_library_dir(profile));
+        std::fs::create_dir_all(directory.join(&args_dir))?;
+        for args_file in &["unix_args.txt", "win_args.txt"] {
+            let bytes = read_entry(installer, &format!("data/{}", args_file))?;
+            std::fs::write(directory.join(&args_dir).join(args_file), bytes)?;
+        }
+
+        //Left alone if it's there so people can keep their memory settings
+        let user_args = directory.join("user_jvm_args.txt");
+        if !user_args.exists() {
+            let bytes = read_entry(installer, "data/user_jvm_args.txt").unwrap_or_default();
+            std::fs::write(user_args, bytes)?;
+        }
+
+        let args_file = |name: &str| format!("@{}", args_dir.join(name).to_string_lossy().replace('\\', "/"));
+        return server::write_platform_start_scripts(directory,
+            &[String::from("@user_jvm_args.txt"), args_file("unix_args.txt")],
+            &[String::from("@user_jvm_args.txt"), args_file("win_args.txt").replace('/', "\\")]);
+    }
+
+    let path = match &profile.path {
+        Some(path) => path,
+        None => return Err(InstallError::Unsupported(
+                format!("{} doesn't say how to start the server", profile.version))),
+    };
+    let jar = library_path(lib_dir, path)?;
+    let name = jar.file_name()
+                  .map(|name| name.to_string_lossy().into_owned())
+                  .unwrap_or_default();
+    store::link_or_copy(&jar, &directory.join(&name))?;
+    server::write_start_scripts(directory, &[String::from("-jar"), name])
+}
+
+//Where the loader's own jars live in the libraries. install_profile versions look like
+//1.20.1-forge-47.1.0 while the maven uses 1.20.1-47.1.0, and neoforge-20.2.86 for neoforge
+fn loader_library_dir(profile: &InstallProfile) -> PathBuf {
+    match profile.version.strip_prefix("neoforge-") {
+        Some(version) => Path::new("net/neoforged/neoforge").join(version),
+        None => Path::new("net/minecraftforge/forge").join(profile.version.replacen("-forge-", "-", 1)),
+    }
+}
+
+fn read_entry(installer: &mut Installer, name: &str) -> Result<Vec<u8>, InstallError> {
+    let mut entry = installer.by_name(name)?;
+    let mut bytes = Vec::new();
+    entry.read_to_end(&mut bytes)?;
+    Ok(bytes)
+}
+
+fn jar_main_class(jar: &Path) -> Result<String, InstallError> {
+    let mut jar_file = zip::ZipArchive::new(std::fs::File::open(jar)?)?;
+    let mut manifest = String::new();
+    jar_file.by_name("META-INF/MANIFEST.MF")?.read_to_string(&mut manifest)?;
+
+    manifest.lines()
+            .find_map(|line| line.strip_prefix("Main-Class:"))
+            .map(|main_class| main_class.trim().to_string())
+            .ok_or_else(|| InstallError::Unsupported(format!("{} has no main class", jar.display())))
+}
+
+//The instance's java comes first, then JAVA_HOME when it's set, otherwise hopes java is on the PATH
+fn java_command(instance_java: Option<String>) -> PathBuf {
+    match (instance_java, std::env::var_os("JAVA_HOME")) {
+        (Some(java), _) => PathBuf::from(java),
+        (None, Some(home)) => Path::new(&home).join("bin").join("java"),
+        (None, None) => PathBuf::from("java"),
+    }
+}
+
+fn artifact_path(name: &str) -> Result<String, InstallError> {
+    Ok(name.parse::<MavenCoordinate>()?.path())
+}
+
+//Installers say where their files go, which is only trusted as far as the libraries folder
+fn library_path(lib_dir: &Path, name: &str) -> Result<PathBuf, InstallError> {
+    instance_path(lib_dir, &artifact_path(name)?)
+}
+
+#[derive(Serialize, Deserialize, Debug)]
+pub struct InstallProfile {
+    #[serde(default)]
+    pub spec: u32,
+    pub profile: String,
+    pub version: String,
+    pub minecraft: String,
+    pub json: String,
+    pub path: Option<String>,
+    #[serde(rename = "serverJarPath")]
+    pub server_jar_path: Option<String>,
+    #[serde(default)]
+    pub data: HashMap<String, SidedData>,
+    #[serde(default)]
+    pub processors: Vec<Processor>,
+    #[serde(default)]
+    pub libraries: Vec<ForgeLibrary>,
+}
+
+#[derive(Serialize, Deserialize, Debug)]
+pub struct SidedData {
+    pub client: String,
+    pub server: String,
+}
+
+#[derive(Serialize, Deserialize, Debug)]
+pub struct Processor {
+    pub sides: Option<Vec<String>>,
+    pub jar: String,
+    #[serde(default)]
+    pub classpath: Vec<String>,
+    #[serde(default)]
+    pub args: Vec<String>,
+    #[serde(default)]
+    pub outputs: HashMap<String, String>,
+}
+
+//Just enough of the embedded version.json to find its libraries
+#[derive(Serialize, Deserialize, Debug)]
+struct VersionInfo {
+    #[serde(default)]
+    libraries: Vec<ForgeLibrary>,
+}
+
+#[derive(Serialize, Deserialize, Debug)]
+pub struct ForgeLibrary {
+    pub name: String,
+    pub downloads: Option<ForgeLibraryDownloads>,
+}
+
+#[derive(Serialize, Deserialize, Debug)]
+pub struct ForgeLibraryDownloads {
+    pub artifact: Option<ForgeArtifact>,
+}
+
+#[derive(Serialize, Deserialize, Debug)]
+pub struct ForgeArtifact {
+    pub path: String,
+    pub url: String,
+    pub sha1: Option<String>,
+    pub size: Option<u64>,
+}
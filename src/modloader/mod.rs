@@ -1 +1,289 @@
 pub mod fabric;
+pub mod quilt;
+pub mod forge;
+pub mod neoforge;
+pub mod query;
+
+pub use query::{Stability, Selection, VersionQuery, Versioned};
+
+use super::mc_data::mojang_version_data::{Arguments, MojangVersionData};
+use super::download::{InstallError, download_and_check};
+use super::maven::MavenCoordinate;
+use super::store;
+use serde::{Deserialize, Serialize};
+use std::path::{Path, PathBuf};
+use rayon::prelude::*;
+use sha2::Digest;
+
+//Tried in order for libraries that don't say where they're hosted
+pub const DEFAULT_REPOSITORIES: &[&str] = &[
+    "https://maven.fabricmc.net/",
+    "https://libraries.minecraft.net/",
+    "https://repo.maven.apache.org/maven2/",
+];
+
+//Where an instance's loader profile gets written, next to version_info.json
+pub const PROFILE_FILE: &str = "loader_profile.json";
+
+#[derive(Serialize, Deserialize, Debug, Clone, Copy, PartialEq, Default)]
+pub enum Side {
+    #[default]
+    Client,
+    Server,
+}
+
+impl Side {
+    pub fn name(&self) -> &'static str {
+        match self {
+            Side::Client => "client",
+            Side::Server => "server",
+        }
+    }
+}
+
+//What every mod loader can do, so the binary doesn't need to know which one it's installing
+pub trait ModLoader: Sync {
+    //What the loader is called on the command line, i.e. fabric
+    fn name(&self) -> &'static str;
+
+    //The component uid Prism and MultiMC list the loader under
+    fn prism_uid(&self) -> &'static str;
+
+    //Fabric and quilt run on fabric's intermediary mappings, which Prism needs as a component too
+    fn uses_intermediary(&self) -> bool {
+        false
+    }
+
+    //Minecraft versions the loader has builds for
+    fn game_versions(&self, query: &VersionQuery) -> Result<Vec<GameVersion>, InstallError>;
+
+    //Builds of the loader for a minecraft version matching the query, in the order it asks for
+    fn builds(&self, game_version: &str, query: &VersionQuery) -> Result<Vec<LoaderBuild>, InstallError>;
+
+    //The first build the query selects
+    fn resolve_build(&self, game_version: &str, query: &VersionQuery) -> Result<LoaderBuild, InstallError> {
+        self.builds(game_version, query)?
+            .into_iter()
+            .next()
+            .ok_or_else(|| InstallError::Unsupported(match &query.version {
+                Some(build) => format!("no {} build {} for {}", self.name(), build, game_version),
+                None => format!("no matching {} builds for {}", self.name(), game_version),
+            }))
+    }
+
+    //Whether install can put the loader on that side, so nothing gets downloaded for one it can't
+    fn supports(&self, _side: Side) -> bool {
+        true
+    }
+
+    //Whether the official launcher can run the profile as it is, without anything else installing it
+    fn launcher_ready(&self) -> bool {
+        true
+    }
+
+    //Installs the loader on top of a vanilla client or server already in the directory
+    fn install(&self, build: &LoaderBuild, vanilla: &MojangVersionData, directory: &Path, side: Side) -> Result<(), InstallError>;
+
+    //The version profile install leaves behind, for launchers
+    fn profile(&self, build: &LoaderBuild, side: Side) -> Result<serde_json::Value, InstallError>;
+}
+
+//Every loader the crate knows about
+pub fn loaders() -> Vec<Box<dyn ModLoader>> {
+    vec![
+        Box::new(fabric::Fabric),
+        Box::new(quilt::Quilt),
+        Box::new(forge::Forge),
+        Box::new(neoforge::NeoForge),
+    ]
+}
+
+pub fn get_loader(name: &str) -> Option<Box<dyn ModLoader>> {
+    loaders().into_iter()
+             .find(|loader| loader.name().eq_ignore_ascii_case(name))
+}
+
+//Looks up a loader and build from name[@build]. Without a build it's the newest stable one, or
+//the newest of all for loaders that don't mark any builds stable.
+pub fn resolve_loader(spec: &str, game_version: &str) -> Result<(Box<dyn ModLoader>, LoaderBuild), InstallError> {
+    let mut spec = spec.splitn(2, '@');
+    let name = spec.next().unwrap_or("");
+    let build = spec.next().filter(|build| !build.is_empty());
+
+    let loader = get_loader(name).ok_or_else(|| {
+        let names: Vec<&str> = loaders().iter().map(|l| l.name()).collect();
+        InstallError::Unsupported(format!("unknown loader {}, expected one of {}", name, names.join(", ")))
+    })?;
+
+    let build = match build {
+        Some(build) => loader.resolve_build(game_version, &VersionQuery::exact(build))?,
+        None => match loader.resolve_build(game_version, &VersionQuery::stable()) {
+            Ok(build) => build,
+            Err(_) => loader.resolve_build(game_version, &VersionQuery::default())?,
+        },
+    };
+    Ok((loader, build))
+}
+
+#[derive(Serialize, Deserialize, Debug, Clone)]
+pub struct GameVersion {
+    pub version: String,
+    pub stable: bool,
+}
+
+impl Versioned for GameVersion {
+    fn version(&self) -> &str {
+        &self.version
+    }
+
+    fn build(&self) -> Option<u32> {
+        None
+    }
+
+    fn stable(&self) -> bool {
+        self.stable
+    }
+}
+
+#[derive(Serialize, Deserialize, Debug, Clone)]
+pub struct LoaderBuild {
+    pub loader: String,
+    pub game_version: String,
+    pub version: String,
+    //Only some loaders number their builds
+    pub build: Option<u32>,
+    pub stable: bool,
+}
+
+impl Versioned for LoaderBuild {
+    fn version(&self) -> &str {
+        &self.version
+    }
+
+    fn build(&self) -> Option<u32> {
+        self.build
+    }
+
+    fn stable(&self) -> bool {
+        self.stable
+    }
+}
+
+//A version profile in the format launchers expect, layered on top of the vanilla version it
+//inherits from
+#[derive(Serialize, Deserialize, Debug)]
+pub struct LoaderProfile {
+    pub id: String,
+    #[serde(rename = "inheritsFrom")]
+    pub inherits_from: String,
+    #[serde(rename = "releaseTime")]
+    pub release_time: String,
+    pub time: String,
+    #[serde(rename = "type")]
+    pub release_type: String,
+    #[serde(rename = "mainClass")]
+    pub main_class: String,
+    pub arguments: Arguments,
+    pub libraries: Vec<ProfileLibrary>,
+}
+
+#[derive(Serialize, Deserialize, Debug, Clone)]
+pub struct ProfileLibrary {
+    pub name: String,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub url: Option<String>,
+}
+
+//Launchers expect the same timestamp format Mojang uses
+pub fn profile_timestamp() -> String {
+    chrono::Utc::now().format("%Y-%m-%dT%H:%M:%S+00:00").to_string()
+}
+
+//Downloads every library in a loader profile into ./libraries, returning where each jar ended up.
+//Libraries are fetched in parallel the same way vanilla libraries are.
+pub fn install_libraries(
+    libraries: &[ProfileLibrary],
+    client: &reqwest::blocking::Client,
+) -> Result<Vec<PathBuf>, InstallError> {
+    install_libraries_to(libraries, Path::new("./libraries"), client)
+}
+
+//Same as install_libraries, into some other library store
+pub fn install_libraries_to(
+    libraries: &[ProfileLibrary],
+    lib_path: &Path,
+    client: &reqwest::blocking::Client,
+) -> Result<Vec<PathBuf>, InstallError> {
+    libraries.par_iter()
+             .map(|lib| install_library(lib, lib_path, client))
+             .collect()
+}
+
+fn install_library(
+    lib: &ProfileLibrary,
+    lib_path: &Path,
+    client: &reqwest::blocking::Client,
+) -> Result<PathBuf, InstallError> {
+    let coordinate: MavenCoordinate = lib.name.parse()?;
+    let location = lib_path.join(coordinate.path());
+
+    let repositories: Vec<&str> = match &lib.url {
+        Some(url) => vec![url],
+        None => DEFAULT_REPOSITORIES.to_vec(),
+    };
+
+    //The first repository that publishes a hash for the jar is the one that has it
+    for repository in repositories {
+        let url = coordinate.url(repository);
+        let sha1 = match fetch_hash(client, &format!("{}.sha1", url))? {
+            Some(sha1) => sha1,
+            None => continue,
+        };
+
+        let up_to_date = match std::fs::read(&location) {
+            Err(_) => false,
+            Ok(b) => sha1::Sha1::from(b).digest().to_string() == sha1,
+        };
+        if up_to_date {
+            return Ok(location);
+        }
+
+        let bytes = download_and_check(&url, Some(client))?;
+        check_hash(&url, "sha1", &sha1::Sha1::from(&bytes).digest().to_string(), &sha1)?;
+
+        //Stronger hashes are only published by some repositories
+        if let Some(sha256) = fetch_hash(client, &format!("{}.sha256", url))? {
+            check_hash(&url, "sha256", &format!("{:x}", sha2::Sha256::digest(&bytes)), &sha256)?;
+        }
+        if let Some(sha512) = fetch_hash(client, &format!("{}.sha512", url))? {
+            check_hash(&url, "sha512", &format!("{:x}", sha2::Sha512::digest(&bytes)), &sha512)?;
+        }
+
+        if let Some(parent) = location.parent() {
+            std::fs::create_dir_all(parent)?;
+        }
+        store::place(&bytes, &location)?;
+        println!("Installed {}", lib.name);
+        return Ok(location);
+    }
+
+    Err(InstallError::Unsupported(format!("couldn't find {} in any repository", lib.name)))
+}
+
+//Hash files sometimes have the file name after the hash, so only the first word counts
+fn fetch_hash(client: &reqwest::blocking::Client, url: &str) -> Result<Option<String>, InstallError> {
+    let response = client.get(url).send()?;
+    if !response.status().is_success() {
+        return Ok(None);
+    }
+    let text = response.text()?;
+    Ok(text.split_whitespace().next().map(|hash| hash.to_lowercase()))
+}
+
+fn check_hash(url: &str, algorithm: &str, actual: &str, expected: &str) -> Result<(), InstallError> {
+    if actual != expected {
+        return Err(InstallError::HashError(
+                format!("{} has {} {}, expected {}", url, algorithm, actual, expected)));
+    }
+    Ok(())
+}
@@ -0,0 +1,128 @@
+use std::path::Path;
+use serde::{Serialize, Deserialize};
+use super::super::download::InstallError;
+use super::super::mc_data::mojang_version_data::MojangVersionData;
+use super::super::maven::MavenCoordinate;
+use super::forge::{download_installer, install_from_installer, installer_profile};
+use super::{Side, ModLoader, GameVersion, LoaderBuild, VersionQuery};
+
+const MAVEN_URL: &str = "https://maven.neoforged.net/releases";
+
+#[derive(Serialize, Deserialize, Debug, Clone)]
+pub struct NeoForgeVersion {
+    //The version of minecraft this build is for, i.e. 1.20.4
+    pub minecraft: String,
+    //What the maven calls it, i.e. 20.4.237
+    pub version: String,
+}
+
+impl NeoForgeVersion {
+    pub fn installer_url(&self) -> String {
+        format!("{}/net/neoforged/neoforge/{}/neoforge-{}-installer.jar", MAVEN_URL, self.version, self.version)
+    }
+
+    pub fn is_stable(&self) -> bool {
+        !self.version.contains("-beta")
+    }
+}
+
+//Lists every neoforge build for a minecraft version, newest first
+pub fn get_neoforge_versions(mc_version: &str) -> Result<Vec<NeoForgeVersion>, InstallError> {
+    Ok(get_all_neoforge_versions()?.into_iter()
+           .filter(|version| version.minecraft == mc_version)
+           .collect())
+}
+
+//Lists every neoforge build there is, newest first
+pub fn get_all_neoforge_versions() -> Result<Vec<NeoForgeVersion>, InstallError> {
+    let client = reqwest::blocking::Client::new();
+    let neoforge: MavenCoordinate = "net.neoforged:neoforge:0".parse()?;
+    let metadata = neoforge.metadata(MAVEN_URL, &client)?;
+
+    let mut versions: Vec<NeoForgeVersion> = metadata.versions.into_iter()
+        .filter_map(|version| Some(NeoForgeVersion { minecraft: minecraft_version(&version)?, version }))
+        .collect();
+    versions.reverse();
+    Ok(versions)
+}
+
+//Neoforge versions drop the leading "1." from the minecraft version they're for, so 20.4.237 is
+//for 1.20.4, and 21.0.10 is for 1.21
+fn minecraft_version(version: &str) -> Option<String> {
+    let mut parts = version.split('.');
+    let major = parts.next()?;
+    let minor = parts.next()?;
+    if major.parse::<u32>().is_err() || minor.parse::<u32>().is_err() {
+        return None;
+    }
+
+    if minor == "0" {
+        Some(format!("1.{}", major))
+    } else {
+        Some(format!("1.{}.{}", major, minor))
+    }
+}
+
+pub struct NeoForge;
+
+impl ModLoader for NeoForge {
+    fn name(&self) -> &'static str {
+        "neoforge"
+    }
+
+    fn prism_uid(&self) -> &'static str {
+        "net.neoforged"
+    }
+
+    fn game_versions(&self, query: &VersionQuery) -> Result<Vec<GameVersion>, InstallError> {
+        let mut versions: Vec<GameVersion> = Vec::new();
+        for version in get_all_neoforge_versions()? {
+            if !versions.iter().any(|v| v.version == version.minecraft) {
+                versions.push(GameVersion { version: version.minecraft, stable: true });
+            }
+        }
+        Ok(query.filter(versions))
+    }
+
+    fn builds(&self, game_version: &str, query: &VersionQuery) -> Result<Vec<LoaderBuild>, InstallError> {
+        let builds = get_neoforge_versions(game_version)?.into_iter()
+               .map(|v| LoaderBuild {
+                   loader: String::from(self.name()),
+                   game_version: String::from(game_version),
+                   stable: v.is_stable(),
+                   version: v.version,
+                   build: None,
+               })
+               .collect();
+        Ok(query.apply(builds))
+    }
+
+    //The installer's processors have to run before the launcher can use the profile
+    fn launcher_ready(&self) -> bool {
+        false
+    }
+
+    fn install(&self, build: &LoaderBuild, vanilla: &MojangVersionData, directory: &Path, side: Side) -> Result<(), InstallError> {
+        let version = NeoForgeVersion { minecraft: build.game_version.clone(), version: build.version.clone() };
+        match side {
+            Side::Client => install_neoforge_at_instance(&version, vanilla, directory),
+            Side::Server => install_neoforge_server_at_instance(&version, vanilla, directory),
+        }
+    }
+
+    fn profile(&self, build: &LoaderBuild, _side: Side) -> Result<serde_json::Value, InstallError> {
+        let version = NeoForgeVersion { minecraft: build.game_version.clone(), version: build.version.clone() };
+        installer_profile(download_installer(&version.installer_url())?)
+    }
+}
+
+pub fn install_neoforge_at_instance(version: &NeoForgeVersion, vanilla: &MojangVersionData, instance_dir: &Path) -> Result<(), InstallError> {
+    let installer = download_installer(&version.installer_url())?;
+    install_from_installer(installer, vanilla, instance_dir, Side::Client)
+}
+
+//Installs on top of a vanilla server installed by server::install_server_to_directory
+pub fn install_neoforge_server_at_instance(version: &NeoForgeVersion, vanilla: &MojangVersionData, server_dir: &Path) -> Result<(), InstallError> {
+    let installer = download_installer(&version.installer_url())?;
+    install_from_installer(installer, vanilla, server_dir, Side::Server)
+}
@@ -0,0 +1,207 @@
+use std::cmp::Ordering;
+
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub enum Stability {
+    Stable,
+    Unstable,
+    Both
+}
+
+//Which end of the matching builds comes first
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub enum Selection {
+    Newest,
+    Oldest,
+}
+
+//Narrows down a listing of game versions or loader builds. Bounds are inclusive and always
+//compared as versions, never as build numbers.
+#[derive(Debug, Clone)]
+pub struct VersionQuery {
+    pub stability: Stability,
+    pub min_version: Option<String>,
+    pub max_version: Option<String>,
+    //Either the exact version or the build number
+    pub version: Option<String>,
+    pub selection: Selection,
+}
+
+impl Default for VersionQuery {
+    fn default() -> Self {
+        VersionQuery {
+            stability: Stability::Both,
+            min_version: None,
+            max_version: None,
+            version: None,
+            selection: Selection::Newest,
+        }
+    }
+}
+
+//Anything a VersionQuery can sort and filter
+pub trait Versioned {
+    fn version(&self) -> &str;
+    fn build(&self) -> Option<u32>;
+    fn stable(&self) -> bool;
+}
+
+impl VersionQuery {
+    pub fn stable() -> Self {
+        VersionQuery { stability: Stability::Stable, ..Default::default() }
+    }
+
+    pub fn exact(version: &str) -> Self {
+        VersionQuery { version: Some(String::from(version)), ..Default::default() }
+    }
+
+    pub fn matches<T: Versioned>(&self, item: &T) -> bool {
+        let stability = match self.stability {
+            Stability::Stable => item.stable(),
+            Stability::Unstable => !item.stable(),
+            Stability::Both => true,
+        };
+        let version = match &self.version {
+            Some(version) => item.version() == version
+                             || item.build().map(|b| b.to_string()).as_deref() == Some(version.as_str()),
+            None => true,
+        };
+        //Bounds are versions, since build numbers only mean something within one minor version
+        let min = match &self.min_version {
+            Some(min) => compare_versions(item.version(), min) != Ordering::Less,
+            None => true,
+        };
+        let max = match &self.max_version {
+            Some(max) => compare_versions(item.version(), max) != Ordering::Greater,
+            None => true,
+        };
+        stability && version && min && max
+    }
+
+    //Filters without reordering, for listings like game versions that are already in order
+    pub fn filter<T: Versioned>(&self, items: Vec<T>) -> Vec<T> {
+        items.into_iter()
+             .filter(|item| self.matches(item))
+             .collect()
+    }
+
+    //Filters, then sorts so the selected end comes first
+    pub fn apply<T: Versioned>(&self, items: Vec<T>) -> Vec<T> {
+        let mut items = self.filter(items);
+        items.sort_by(|a, b| compare(b, a));
+        if self.selection == Selection::Oldest {
+            items.reverse();
+        }
+        items
+    }
+
+    pub fn select<T: Versioned>(&self, items: Vec<T>) -> Option<T> {
+        self.apply(items).into_iter().next()
+    }
+}
+
+//Fabric restarts its build numbers with every minor version (0.14.21 is build 21, 0.15.0 is
+//build 0), so the version has to come first and the build only breaks ties
+fn compare<T: Versioned>(a: &T, b: &T) -> Ordering {
+    compare_versions(a.version(), b.version())
+        .then_with(|| a.build().cmp(&b.build()))
+}
+
+//Compares dotted versions piece by piece, numbers as numbers. Anything after a - is a
+//pre-release, so 0.17.0-beta.3 comes before 0.17.0.
+pub fn compare_versions(a: &str, b: &str) -> Ordering {
+    let (a_release, a_pre) = split_prerelease(a);
+    let (b_release, b_pre) = split_prerelease(b);
+
+    match compare_pieces(a_release, b_release) {
+        Ordering::Equal => match (a_pre, b_pre) {
+            (None, None) => Ordering::Equal,
+            (None, Some(_)) => Ordering::Greater,
+            (Some(_), None) => Ordering::Less,
+            (Some(a), Some(b)) => compare_pieces(a, b),
+        },
+        ordering => ordering,
+    }
+}
+
+fn split_prerelease(version: &str) -> (&str, Option<&str>) {
+    //Build metadata after a + doesn't count
+    let version = version.split('+').next().unwrap_or(version);
+    match version.find('-') {
+        Some(dash) => (&version[..dash], Some(&version[dash + 1..])),
+        None => (version, None),
+    }
+}
+
+fn compare_pieces(a: &str, b: &str) -> Ordering {
+    let mut a_pieces = a.split(|c: char| !c.is_ascii_alphanumeric());
+    let mut b_pieces = b.split(|c: char| !c.is_ascii_alphanumeric());
+    loop {
+        let ordering = match (a_pieces.next(), b_pieces.next()) {
+            (None, None) => return Ordering::Equal,
+            (Some(_), None) => return Ordering::Greater,
+            (None, Some(_)) => return Ordering::Less,
+            (Some(a), Some(b)) => match (a.parse::<u64>(), b.parse::<u64>()) {
+                (Ok(a), Ok(b)) => a.cmp(&b),
+                _ => a.cmp(b),
+            },
+        };
+        if ordering != Ordering::Equal {
+            return ordering;
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    struct Build(&'static str, Option<u32>);
+
+    impl Versioned for Build {
+        fn version(&self) -> &str { self.0 }
+        fn build(&self) -> Option<u32> { self.1 }
+        fn stable(&self) -> bool { true }
+    }
+
+    #[test]
+    fn splits_prerelease_and_metadata() {
+        assert_eq!(split_prerelease("0.17.0"), ("0.17.0", None));
+        assert_eq!(split_prerelease("0.17.0-beta.3"), ("0.17.0", Some("beta.3")));
+        assert_eq!(split_prerelease("0.17.0-beta.3+build.5"), ("0.17.0", Some("beta.3")));
+        assert_eq!(split_prerelease("1.20.1+local"), ("1.20.1", None));
+    }
+
+    #[test]
+    fn prerelease_comes_before_release() {
+        assert_eq!(compare_versions("0.17.0-beta.3", "0.17.0"), Ordering::Less);
+        assert_eq!(compare_versions("0.17.0", "0.17.0-beta.3"), Ordering::Greater);
+        assert_eq!(compare_versions("0.17.0-beta.3", "0.16.9"), Ordering::Greater);
+        assert_eq!(compare_versions("0.17.0-beta.2", "0.17.0-beta.10"), Ordering::Less);
+        assert_eq!(compare_versions("0.17.0+build.1", "0.17.0+build.2"), Ordering::Equal);
+    }
+
+    #[test]
+    fn numbers_compare_as_numbers() {
+        assert_eq!(compare_pieces("0.14.9", "0.14.21"), Ordering::Less);
+        assert_eq!(compare_pieces("1.10", "1.9"), Ordering::Greater);
+        assert_eq!(compare_pieces("1.20", "1.20.1"), Ordering::Less);
+        assert_eq!(compare_pieces("47.1.84", "47.1.84"), Ordering::Equal);
+        assert_eq!(compare_pieces("beta", "alpha"), Ordering::Greater);
+    }
+
+    #[test]
+    fn bounds_are_inclusive_versions() {
+        let query = VersionQuery {
+            min_version: Some(String::from("0.14.21")),
+            max_version: Some(String::from("0.15.0")),
+            ..Default::default()
+        };
+        //Fabric's build numbers would put 0.15.0 (build 0) below 0.14.21 (build 21)
+        assert!(query.matches(&Build("0.14.21", Some(21))));
+        assert!(query.matches(&Build("0.15.0", Some(0))));
+        assert!(query.matches(&Build("0.15.0-beta.1", Some(0))));
+        assert!(!query.matches(&Build("0.14.9", Some(9))));
+        assert!(!query.matches(&Build("0.15.1", Some(1))));
+        assert!(!query.matches(&Build("0.14.21-beta.1", None)));
+    }
+}
@@ -0,0 +1,234 @@
+use std::path::Path;
+use serde::{Serialize, Deserialize};
+use super::super::download::InstallError;
+use super::super::mc_data::mojang_version_data::Arguments;
+use super::super::mc_data::mojang_version_data::MojangVersionData;
+use super::{LoaderProfile, ProfileLibrary, Side, PROFILE_FILE, profile_timestamp, install_libraries};
+use super::{ModLoader, GameVersion, LoaderBuild, VersionQuery, Versioned};
+
+const VERSION_URL: &str = "https://meta.quiltmc.org/v3/versions";
+
+const MAVEN_URL: &str = "https://maven.quiltmc.org/repository/release/";
+//Quilt reuses fabric's intermediary mappings
+const FABRIC_MAVEN_URL: &str = "https://maven.fabricmc.net/";
+
+pub struct Quilt;
+
+impl ModLoader for Quilt {
+    fn name(&self) -> &'static str {
+        "quilt"
+    }
+
+    fn prism_uid(&self) -> &'static str {
+        "org.quiltmc.quilt-loader"
+    }
+
+    fn uses_intermediary(&self) -> bool {
+        true
+    }
+
+    fn game_versions(&self, query: &VersionQuery) -> Result<Vec<GameVersion>, InstallError> {
+        Ok(get_game_versions(query)?.into_iter()
+               .map(|v| GameVersion { version: v.version, stable: v.stable })
+               .collect())
+    }
+
+    fn builds(&self, game_version: &str, query: &VersionQuery) -> Result<Vec<LoaderBuild>, InstallError> {
+        let version = QuiltGameVersion { version: String::from(game_version), stable: true };
+        Ok(get_quilt_builds_from_version(&version, query)?.into_iter()
+               .map(|b| LoaderBuild {
+                   loader: String::from(self.name()),
+                   game_version: String::from(game_version),
+                   stable: b.loader.is_stable(),
+                   version: b.loader.version,
+                   build: None,
+               })
+               .collect())
+    }
+
+    fn supports(&self, side: Side) -> bool {
+        side == Side::Client
+    }
+
+    fn install(&self, build: &LoaderBuild, _vanilla: &MojangVersionData, directory: &Path, side: Side) -> Result<(), InstallError> {
+        match side {
+            Side::Client => install_quilt_at_instance(get_quilt_build(&build.game_version, &build.version)?, directory),
+            Side::Server => Err(InstallError::Unsupported(String::from("quilt servers aren't supported yet"))),
+        }
+    }
+
+    fn profile(&self, build: &LoaderBuild, side: Side) -> Result<serde_json::Value, InstallError> {
+        let build = get_quilt_build(&build.game_version, &build.version)?;
+        Ok(serde_json::to_value(build.profile(side))?)
+    }
+}
+
+pub fn install_quilt_at_instance(build: QuiltBuild, instance_dir: &Path) -> Result<(), InstallError> {
+    let client = reqwest::blocking::Client::new();
+    let profile = build.profile(Side::Client);
+    install_libraries(&profile.libraries, &client)?;
+
+    let profile = serde_json::to_string_pretty(&profile)?;
+    std::fs::write(instance_dir.join(PROFILE_FILE), profile)?;
+    Ok(())
+}
+
+pub fn get_quilt_build(game_version: &str, loader_version: &str) -> Result<QuiltBuild, reqwest::Error> {
+    reqwest::blocking::get(reqwest::Url::parse(&format!("{}/loader/{}/{}", VERSION_URL, game_version, loader_version)).unwrap())?
+        .error_for_status()?
+        .json()
+}
+
+//Builds come back sorted by the query rather than in whatever order the API uses
+pub fn get_quilt_builds_from_version(version: &QuiltGameVersion, query: &VersionQuery) -> Result<Vec<QuiltBuild>, reqwest::Error> {
+    let result: Vec<QuiltBuild> = reqwest::blocking::get(reqwest::Url::parse(&format!("{}/loader/{}", VERSION_URL, version.version)).unwrap())?.error_for_status()?.json()?;
+    Ok(query.apply(result))
+}
+
+#[derive(Serialize, Deserialize, Debug)]
+pub struct QuiltBuild {
+    pub loader: QuiltBuildLoader,
+    pub hashed: QuiltBuildMappings,
+    pub intermediary: Option<QuiltBuildMappings>,
+    #[serde(alias = "launcherMeta")]
+    pub launcher_meta: QuiltBuildMeta
+}
+
+impl Versioned for QuiltBuild {
+    fn version(&self) -> &str {
+        &self.loader.version
+    }
+
+    fn build(&self) -> Option<u32> {
+        None
+    }
+
+    fn stable(&self) -> bool {
+        self.loader.is_stable()
+    }
+}
+
+impl QuiltBuild {
+    //The same profile meta.quiltmc.org serves from /loader/<game>/<loader>/profile/json
+    pub fn profile(&self, side: Side) -> LoaderProfile {
+        //Mappings are versioned after the game version they map
+        let game_version = &self.hashed.version;
+        let libraries = &self.launcher_meta.libraries;
+        let side_libraries = match side {
+            Side::Client => &libraries.client,
+            Side::Server => &libraries.server,
+        };
+
+        let mut profile_libraries: Vec<ProfileLibrary> = libraries.common.iter()
+                    .chain(side_libraries.iter())
+                    .map(|lib| ProfileLibrary { name: lib.name.clone(), url: lib.url.clone() })
+                    .collect();
+        //Newer loaders run on fabric's intermediary instead of quilt's hashed mappings
+        profile_libraries.push(match &self.intermediary {
+            Some(intermediary) => ProfileLibrary {
+                name: intermediary.maven.clone(),
+                url: Some(String::from(FABRIC_MAVEN_URL)),
+            },
+            None => ProfileLibrary {
+                name: self.hashed.maven.clone(),
+                url: Some(String::from(MAVEN_URL)),
+            },
+        });
+        profile_libraries.push(ProfileLibrary {
+            name: self.loader.maven.clone(),
+            url: Some(String::from(MAVEN_URL)),
+        });
+
+        let main_class = match side {
+            Side::Client => &self.launcher_meta.main_class.client,
+            Side::Server => &self.launcher_meta.main_class.server,
+        };
+
+        let time = profile_timestamp();
+        LoaderProfile {
+            id: format!("quilt-loader-{}-{}", self.loader.version, game_version),
+            inherits_from: game_version.clone(),
+            release_time: time.clone(),
+            time,
+            release_type: String::from("release"),
+            main_class: main_class.clone(),
+            arguments: Arguments { game: Vec::new(), jvm: Vec::new() },
+            libraries: profile_libraries,
+        }
+    }
+}
+
+#[derive(Serialize, Deserialize, Debug)]
+pub struct QuiltBuildLoader {
+    pub separator: String,
+    #[serde(default)]
+    pub build: u32,
+    pub maven: String,
+    pub version: String,
+}
+
+impl QuiltBuildLoader {
+    //Quilt doesn't flag stable builds like fabric does, pre-releases are marked in the version
+    //instead, i.e. 0.17.0-beta.3
+    pub fn is_stable(&self) -> bool {
+        !self.version.contains('-')
+    }
+}
+
+#[derive(Serialize, Deserialize, Debug)]
+pub struct QuiltBuildMappings {
+    maven: String,
+    version: String,
+}
+
+#[derive(Serialize, Deserialize, Debug)]
+pub struct QuiltBuildMeta {
+    version: u16,
+    libraries: QuiltBuildLibraries,
+    #[serde(alias = "mainClass")]
+    main_class: QuiltMainClass
+}
+
+#[derive(Serialize, Deserialize, Debug)]
+pub struct QuiltMainClass {
+    client: String,
+    server: String
+}
+
+#[derive(Serialize, Deserialize, Debug)]
+pub struct QuiltBuildLibraries {
+    client: Vec<QuiltLibrary>,
+    common: Vec<QuiltLibrary>,
+    server: Vec<QuiltLibrary>
+}
+
+#[derive(Serialize, Deserialize, Debug)]
+pub struct QuiltLibrary {
+    name: String,
+    url: Option<String>
+}
+
+pub fn get_game_versions(query: &VersionQuery) -> Result<Vec<QuiltGameVersion>, reqwest::Error> {
+    let result: Vec<QuiltGameVersion> = reqwest::blocking::get(reqwest::Url::parse(&format!("{}/game", VERSION_URL)).unwrap())?.error_for_status()?.json()?;
+    Ok(query.filter(result))
+}
+
+#[derive(Serialize, Deserialize, Debug)]
+pub struct QuiltGameVersion {
+    pub version: String,
+    pub stable: bool
+}
+
+impl Versioned for QuiltGameVersion {
+    fn version(&self) -> &str {
+        &self.version
+    }
+
+    fn build(&self) -> Option<u32> {
+        None
+    }
+
+    fn stable(&self) -> bool {
+        self.stable
+    }
+}
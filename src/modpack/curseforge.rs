@@ -0,0 +1,389 @@
+use std::collections::HashMap;
+use std::path::{Path, PathBuf};
+use serde::{Serialize, Deserialize};
+use rayon::prelude::*;
+use sha2::Digest;
+use super::super::download::{InstallError, download_and_check};
+use super::super::lockfile;
+use super::super::instance::{self, PackFile, PackInfo};
+use super::super::modloader;
+use super::super::modrinth::MODS_DIR;
+use super::super::store;
+use super::{ImportOptions, extract_overrides, instance_path};
+
+pub const MANIFEST_FILE: &str = "manifest.json";
+
+pub const DEFAULT_API_URL: &str = "https://api.curseforge.com";
+//Either of these can point the importer somewhere else, i.e. a local stand-in for the API
+pub const API_URL_VAR: &str = "CURSEFORGE_API_URL";
+pub const API_KEY_VAR: &str = "CURSEFORGE_API_KEY";
+
+//What CurseForge calls each loader in modLoaders ids, next to what --loader calls it
+const LOADER_PREFIXES: &[(&str, &str)] = &[
+    ("forge-", "forge"),
+    ("neoforge-", "neoforge"),
+    ("fabric-", "fabric"),
+    ("quilt-", "quilt"),
+];
+
+//Where files of each of CurseForge's classes go, anything else is taken to be a mod
+const CLASS_FOLDERS: &[(u32, &str)] = &[
+    (6, MODS_DIR),
+    (12, "resourcepacks"),
+    (6552, "shaderpacks"),
+];
+
+#[derive(Debug, Clone)]
+pub struct CurseForgeApi {
+    pub base_url: String,
+    pub api_key: Option<String>,
+}
+
+impl CurseForgeApi {
+    pub fn from_env() -> Self {
+        CurseForgeApi {
+            base_url: std::env::var(API_URL_VAR).unwrap_or_else(|_| String::from(DEFAULT_API_URL)),
+            api_key: std::env::var(API_KEY_VAR).ok(),
+        }
+    }
+
+    fn post<T: Serialize>(&self, client: &reqwest::blocking::Client, endpoint: &str, body: &T) -> reqwest::blocking::RequestBuilder {
+        let request = client.post(&format!("{}/v1/{}", self.base_url.trim_end_matches('/'), endpoint))
+                            .json(body);
+        match &self.api_key {
+            Some(key) => request.header("x-api-key", key),
+            None => request,
+        }
+    }
+
+    pub fn get_files(&self, client: &reqwest::blocking::Client, file_ids: &[u32]) -> Result<Vec<CurseFile>, InstallError> {
+        let response: DataResponse<CurseFile> = self.post(client, "mods/files", &FileIdsRequest { file_ids: file_ids.to_vec() })
+                                                    .send()?
+                                                    .error_for_status()?
+                                                    .json()?;
+        Ok(response.data)
+    }
+
+    pub fn get_mods(&self, client: &reqwest::blocking::Client, mod_ids: &[u32]) -> Result<Vec<CurseMod>, InstallError> {
+        let response: DataResponse<CurseMod> = self.post(client, "mods", &ModIdsRequest { mod_ids: mod_ids.to_vec() })
+                                                   .send()?
+                                                   .error_for_status()?
+                                                   .json()?;
+        Ok(response.data)
+    }
+}
+
+//A file whose author doesn't allow downloads outside of CurseForge
+#[derive(Debug, Clone)]
+pub struct ManualDownload {
+    pub name: String,
+    pub file_name: String,
+    pub url: String,
+    //Where to put it once it's downloaded
+    pub path: PathBuf,
+}
+
+//Installs the pack's minecraft and loader, then its files and overrides on top. Files that have to
+//be downloaded by hand are handed back instead of failing the import.
+pub fn import_curseforge(path: &Path, options: &ImportOptions, api: &CurseForgeApi) -> Result<(PathBuf, Vec<ManualDownload>), InstallError> {
+    let mut archive = zip::ZipArchive::new(std::fs::File::open(path)?)?;
+    let manifest: CurseManifest = serde_json::from_reader(archive.by_name(MANIFEST_FILE)?)?;
+
+    if manifest.manifest_type != "minecraftModpack" {
+        return Err(InstallError::Unsupported(format!("{} is a {}, not a modpack", path.display(), manifest.manifest_type)));
+    }
+
+    let mc_version = &manifest.minecraft.version;
+    let loader_build = match manifest.loader_spec()? {
+        Some(spec) => Some(modloader::resolve_loader(&spec, mc_version)?),
+        None => None,
+    };
+    let name = options.name.clone().unwrap_or_else(|| instance::name_from(&manifest.name));
+    let (directory, mut instance_manifest) = instance::install(&name, mc_version, loader_build.as_ref(), options.side, options.accept_eula, None)?;
+
+    let client = reqwest::blocking::Client::new();
+    let wanted: Vec<&CurseManifestFile> = manifest.files.iter()
+                                                  .filter(|file| file.required || options.include_optional)
+                                                  .collect();
+    let file_ids: Vec<u32> = wanted.iter().map(|file| file.file_id).collect();
+    let files = if file_ids.is_empty() { Vec::new() } else { api.get_files(&client, &file_ids)? };
+    if let Some(missing) = wanted.iter().find(|w| !files.iter().any(|f| f.id == w.file_id)) {
+        return Err(InstallError::Unsupported(
+                format!("{} has no file {} for project {}", api.base_url, missing.file_id, missing.project_id)));
+    }
+
+    //The files don't say what they are or where their project page is, only the projects do
+    let mut mod_ids: Vec<u32> = files.iter().map(|f| f.mod_id).collect();
+    mod_ids.sort_unstable();
+    mod_ids.dedup();
+    let mods: HashMap<u32, CurseMod> = if mod_ids.is_empty() {
+        HashMap::new()
+    } else {
+        api.get_mods(&client, &mod_ids)?.into_iter().map(|m| (m.id, m)).collect()
+    };
+
+    let results = files.par_iter()
+                       .map(|file| install_file(file, mods.get(&file.mod_id), &directory, &client))
+                       .collect::<Result<Vec<Outcome>, InstallError>>()?;
+
+    let overrides = manifest.overrides.as_deref().unwrap_or("overrides");
+    extract_overrides(&mut archive, overrides, &directory)?;
+
+    let mut manual = Vec::new();
+    instance_manifest.files.clear();
+    for result in results {
+        match result {
+            Outcome::Installed(file) => instance_manifest.files.push(file),
+            Outcome::Manual(download) => manual.push(download),
+            Outcome::AlreadyThere => (),
+        }
+    }
+    instance_manifest.pack = Some(PackInfo {
+        name: manifest.name.clone(),
+        version: manifest.version.clone(),
+        summary: None,
+    });
+    instance_manifest.save(&directory)?;
+    lockfile::update_mods(&directory, &instance_manifest)?;
+    println!("Imported {} {}", manifest.name, manifest.version);
+    Ok((directory, manual))
+}
+
+enum Outcome {
+    Installed(PackFile),
+    Manual(ManualDownload),
+    //Downloaded by hand on an earlier import
+    AlreadyThere,
+}
+
+fn install_file(file: &CurseFile, project: Option<&CurseMod>, directory: &Path, client: &reqwest::blocking::Client) -> Result<Outcome, InstallError> {
+    let folder = project.and_then(|p| CLASS_FOLDERS.iter().find(|(class, _)| Some(*class) == p.class_id))
+                        .map(|(_, folder)| *folder)
+                        .unwrap_or(MODS_DIR);
+    let relative = format!("{}/{}", folder, file.file_name);
+    let location = instance_path(directory, &relative)?;
+
+    let url = match &file.download_url {
+        Some(url) => url,
+        None if location.exists() => return Ok(Outcome::AlreadyThere),
+        None => {
+            let page = project.and_then(|p| p.links.website_url.clone())
+                              .unwrap_or_else(|| format!("https://www.curseforge.com/projects/{}", file.mod_id));
+            return Ok(Outcome::Manual(ManualDownload {
+                name: project.map(|p| p.name.clone()).unwrap_or_else(|| file.display_name.clone()),
+                file_name: file.file_name.clone(),
+                url: format!("{}/files/{}", page.trim_end_matches('/'), file.id),
+                path: location,
+            }));
+        }
+    };
+
+    let sha1 = file.sha1();
+    let bytes = match std::fs::read(&location) {
+        Ok(bytes) if sha1.is_some() && sha1 == Some(sha1::Sha1::from(&bytes).digest().to_string()) => bytes,
+        _ => {
+            let bytes = download_and_check(url, Some(client))?;
+            let actual = sha1::Sha1::from(&bytes).digest().to_string();
+            if let Some(sha1) = &sha1 {
+                if &actual != sha1 {
+                    return Err(InstallError::HashError(format!("{} has sha1 {}, expected {}", url, actual, sha1)));
+                }
+            }
+            std::fs::create_dir_all(directory.join(folder))?;
+            store::place(&bytes, &location)?;
+            println!("downloaded {}", relative);
+            bytes
+        }
+    };
+
+    Ok(Outcome::Installed(PackFile {
+        path: relative,
+        downloads: vec![url.clone()],
+        sha1: sha1::Sha1::from(&bytes).digest().to_string(),
+        sha512: format!("{:x}", sha2::Sha512::digest(&bytes)),
+        size: bytes.len() as u64,
+        env: None,
+    }))
+}
+
+#[derive(Serialize, Deserialize, Debug, Clone)]
+pub struct CurseManifest {
+    pub minecraft: CurseMinecraft,
+    #[serde(rename = "manifestType")]
+    pub manifest_type: String,
+    #[serde(rename = "manifestVersion")]
+    pub manifest_version: u32,
+    pub name: String,
+    #[serde(default)]
+    pub version: String,
+    #[serde(default)]
+    pub author: String,
+    pub files: Vec<CurseManifestFile>,
+    pub overrides: Option<String>,
+}
+
+impl CurseManifest {
+    //The primary loader as name@build, ready for resolve_loader
+    pub fn loader_spec(&self) -> Result<Option<String>, InstallError> {
+        let loader = match self.minecraft.mod_loaders.iter().find(|l| l.primary).or_else(|| self.minecraft.mod_loaders.first()) {
+            Some(loader) => loader,
+            None => return Ok(None),
+        };
+        LOADER_PREFIXES.iter()
+                       .find(|(prefix, _)| loader.id.starts_with(prefix))
+                       .ok_or_else(|| InstallError::Unsupported(format!("{} isn't a supported loader", loader.id)))
+                       .and_then(|(prefix, name)| {
+                           //Neoforge for 1.20.1 came out as net.neoforged:forge, before it had builds
+                           //of its own, and its ids carry the minecraft version, i.e. neoforge-1.20.1-47.1.84
+                           if *name == "neoforge" && self.minecraft.version == "1.20.1" {
+                               return Err(InstallError::Unsupported(format!("{} is neoforge's 1.20.1 fork of forge, which can't be installed yet", loader.id)));
+                           }
+                           Ok(Some(format!("{}@{}", name, &loader.id[prefix.len()..])))
+                       })
+    }
+}
+
+#[derive(Serialize, Deserialize, Debug, Clone)]
+pub struct CurseMinecraft {
+    pub version: String,
+    #[serde(rename = "modLoaders", default)]
+    pub mod_loaders: Vec<CurseModLoader>,
+}
+
+#[derive(Serialize, Deserialize, Debug, Clone)]
+pub struct CurseModLoader {
+    pub id: String,
+    #[serde(default)]
+    pub primary: bool,
+}
+
+#[derive(Serialize, Deserialize, Debug, Clone)]
+pub struct CurseManifestFile {
+    #[serde(rename = "projectID")]
+    pub project_id: u32,
+    #[serde(rename = "fileID")]
+    pub file_id: u32,
+    #[serde(default = "required_default")]
+    pub required: bool,
+}
+
+fn required_default() -> bool {
+    true
+}
+
+#[derive(Serialize, Deserialize, Debug)]
+struct DataResponse<T> {
+    data: Vec<T>,
+}
+
+#[derive(Serialize, Deserialize, Debug)]
+struct FileIdsRequest {
+    #[serde(rename = "fileIds")]
+    file_ids: Vec<u32>,
+}
+
+#[derive(Serialize, Deserialize, Debug)]
+struct ModIdsRequest {
+    #[serde(rename = "modIds")]
+    mod_ids: Vec<u32>,
+}
+
+#[derive(Serialize, Deserialize, Debug, Clone)]
+pub struct CurseFile {
+    pub id: u32,
+    #[serde(rename = "modId")]
+    pub mod_id: u32,
+    #[serde(rename = "displayName")]
+    pub display_name: String,
+    #[serde(rename = "fileName")]
+    pub file_name: String,
+    //Missing when the author doesn't allow third party downloads
+    #[serde(rename = "downloadUrl")]
+    pub download_url: Option<String>,
+    #[serde(default)]
+    pub hashes: Vec<CurseHash>,
+}
+
+impl CurseFile {
+    pub fn sha1(&self) -> Option<String> {
+        self.hashes.iter()
+                   .find(|h| h.algo == 1)
+                   .map(|h| h.value.to_lowercase())
+    }
+}
+
+//algo is 1 for sha1 and 2 for md5
+#[derive(Serialize, Deserialize, Debug, Clone)]
+pub struct CurseHash {
+    pub value: String,
+    pub algo: u32,
+}
+
+#[derive(Serialize, Deserialize, Debug, Clone)]
+pub struct CurseMod {
+    pub id: u32,
+    pub name: String,
+    #[serde(default)]
+    pub slug: String,
+    #[serde(rename = "classId")]
+    pub class_id: Option<u32>,
+    #[serde(default)]
+    pub links: CurseLinks,
+}
+
+#[derive(Serialize, Deserialize, Debug, Clone, Default)]
+pub struct CurseLinks {
+    #[serde(rename = "websiteUrl")]
+    pub website_url: Option<String>,
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn manifest(minecraft: &str, loaders: &[(&str, bool)]) -> CurseManifest {
+        CurseManifest {
+            minecraft: CurseMinecraft {
+                version: String::from(minecraft),
+                mod_loaders: loaders.iter().map(|(id, primary)| CurseModLoader { id: String::from(*id), primary: *primary }).collect(),
+            },
+            manifest_type: String::from("minecraftModpack"),
+            manifest_version: 1,
+            name: String::from("pack"),
+            version: String::new(),
+            author: String::new(),
+            files: Vec::new(),
+            overrides: None,
+        }
+    }
+
+    #[test]
+    fn maps_loader_ids_to_specs() {
+        assert_eq!(manifest("1.20.1", &[("fabric-0.14.21", true)]).loader_spec().unwrap().as_deref(), Some("fabric@0.14.21"));
+        assert_eq!(manifest("1.20.1", &[("forge-47.1.0", true)]).loader_spec().unwrap().as_deref(), Some("forge@47.1.0"));
+        assert_eq!(manifest("1.20.4", &[("neoforge-20.4.237", true)]).loader_spec().unwrap().as_deref(), Some("neoforge@20.4.237"));
+        assert_eq!(manifest("1.19.2", &[("quilt-0.19.1", false), ("forge-43.2.0", true)]).loader_spec().unwrap().as_deref(),
+                   Some("forge@43.2.0"));
+        assert_eq!(manifest("1.20.1", &[]).loader_spec().unwrap(), None);
+        assert!(manifest("1.12.2", &[("liteloader-1.12.2", true)]).loader_spec().is_err());
+    }
+
+    #[test]
+    fn rejects_neoforge_for_1_20_1() {
+        assert!(manifest("1.20.1", &[("neoforge-1.20.1-47.1.84", true)]).loader_spec().is_err());
+        assert!(manifest("1.20.1", &[("neoforge-47.1.84", true)]).loader_spec().is_err());
+    }
+
+    #[test]
+    fn sha1_is_the_lowercased_algo_1_hash() {
+        let file: CurseFile = serde_json::from_str(r#"{
+            "id": 1, "modId": 2, "displayName": "A", "fileName": "a.jar", "downloadUrl": null,
+            "hashes": [{"value": "d41d8cd98f00b204e9800998ecf8427e", "algo": 2},
+                       {"value": "DA39A3EE5E6B4B0D3255BFEF95601890AFD80709", "algo": 1}]
+        }"#).unwrap();
+        assert_eq!(file.sha1().as_deref(), Some("da39a3ee5e6b4b0d3255bfef95601890afd80709"));
+
+        let md5_only = CurseFile { hashes: vec![CurseHash { value: String::from("d41d8cd98f00b204e9800998ecf8427e"), algo: 2 }], ..file };
+        assert_eq!(md5_only.sha1(), None);
+    }
+}
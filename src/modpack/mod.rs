@@ -0,0 +1,220 @@
+pub mod mrpack;
+pub mod curseforge;
+pub mod packwiz;
+pub mod prism;
+
+use std::io::{Read, Seek};
+use std::path::{Component, Path, PathBuf};
+use super::download::InstallError;
+use super::modloader::Side;
+use super::store;
+
+#[derive(Debug, Clone)]
+pub struct ImportOptions {
+    pub side: Side,
+    pub include_optional: bool,
+    pub accept_eula: bool,
+    //The instance to install into, instead of one named after the pack
+    pub name: Option<String>,
+}
+
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub enum PackFormat {
+    Mrpack,
+    CurseForge,
+    Prism,
+}
+
+//Goes by what's inside rather than the extension, since CurseForge packs are plain zips
+pub fn detect_format(path: &Path) -> Result<Option<PackFormat>, InstallError> {
+    let archive = zip::ZipArchive::new(std::fs::File::open(path)?)?;
+    let names: Vec<&str> = archive.file_names().collect();
+    Ok(if names.contains(&mrpack::INDEX_FILE) {
+        Some(PackFormat::Mrpack)
+    } else if names.contains(&curseforge::MANIFEST_FILE) {
+        Some(PackFormat::CurseForge)
+    } else if prism::zip_root(&archive).is_some() {
+        Some(PackFormat::Prism)
+    } else {
+        None
+    })
+}
+
+//Left out of exported packs since installing the pack puts them back
+pub const INSTALLED_FILES: &[&str] = &[
+    "instance.json",
+    "instance.lock",
+    "version_info.json",
+    "loader_profile.json",
+    "client.jar",
+    "client.xml",
+    "server.jar",
+    "minecraft_server.*.jar",
+    "fabric-server-launch.*",
+    "start.sh",
+    "start.bat",
+    "user_jvm_args.txt",
+    "libraries",
+    ".forge-installer",
+    "logs",
+    "crash-reports",
+];
+
+//Packs say where files go relative to the instance. Anything that would land outside of it is
+//refused rather than trusted. Packs are made anywhere, so windows paths are refused everywhere.
+pub fn instance_path(directory: &Path, relative: &str) -> Result<PathBuf, InstallError> {
+    let path = Path::new(relative);
+    if relative.is_empty() || relative.contains(['\\', ':']) || path.components().any(|c| !matches!(c, Component::Normal(_))) {
+        return Err(InstallError::Unsupported(format!("{} points outside of the instance", relative)));
+    }
+    Ok(directory.join(path))
+}
+
+//Copies everything under prefix/ in the pack into the instance, over whatever's already there.
+//Returns how many files were written.
+pub fn extract_overrides<R: Read + Seek>(archive: &mut zip::ZipArchive<R>, prefix: &str, directory: &Path) -> Result<usize, InstallError> {
+    let mut extracted = 0;
+    for i in 0..archive.len() {
+        let mut entry = archive.by_index(i)?;
+        let relative = match entry.enclosed_name().and_then(|name| name.strip_prefix(prefix).ok()) {
+            Some(relative) if !relative.as_os_str().is_empty() => relative.to_path_buf(),
+            _ => continue,
+        };
+        let location = directory.join(relative);
+
+        if entry.is_dir() {
+            std::fs::create_dir_all(&location)?;
+            continue;
+        }
+        if let Some(parent) = location.parent() {
+            std::fs::create_dir_all(parent)?;
+        }
+        store::unlink(&location)?;
+        let mut file = std::fs::File::create(&location)?;
+        std::io::copy(&mut entry, &mut file)?;
+        extracted += 1;
+    }
+    Ok(extracted)
+}
+
+//Every file in the instance, relative to it with / between folders, in a stable order
+pub fn walk_files(directory: &Path) -> Result<Vec<String>, InstallError> {
+    let mut files = Vec::new();
+    let mut folders = vec![PathBuf::new()];
+    while let Some(folder) = folders.pop() {
+        for entry in std::fs::read_dir(directory.join(&folder))? {
+            let entry = entry?;
+            let relative = folder.join(entry.file_name());
+            if entry.file_type()?.is_dir() {
+                folders.push(relative);
+            } else {
+                let parts: Vec<String> = relative.components()
+                                                 .map(|c| c.as_os_str().to_string_lossy().into_owned())
+                                                 .collect();
+                files.push(parts.join("/"));
+            }
+        }
+    }
+    files.sort();
+    Ok(files)
+}
+
+//Exclude patterns are globs where * stays inside a folder and ** doesn't. A pattern that
+//matches a folder covers everything in it, so "logs" and "config/*.bak" both work.
+pub fn is_excluded(path: &str, patterns: &[String]) -> bool {
+    patterns.iter().any(|pattern| {
+        let pattern = pattern.trim_end_matches('/');
+        let mut prefix = String::new();
+        path.split('/').any(|part| {
+            if !prefix.is_empty() {
+                prefix.push('/');
+            }
+            prefix.push_str(part);
+            glob_matches(pattern.as_bytes(), prefix.as_bytes())
+        })
+    })
+}
+
+fn glob_matches(pattern: &[u8], path: &[u8]) -> bool {
+    match pattern {
+        [] => path.is_empty(),
+        //**/ can only stop where a folder starts
+        [b'*', b'*', b'/', rest @ ..] => {
+            (0..=path.len()).filter(|&i| i == 0 || path[i - 1] == b'/')
+                            .any(|i| glob_matches(rest, &path[i..]))
+        }
+        [b'*', b'*', rest @ ..] => (0..=path.len()).any(|i| glob_matches(rest, &path[i..])),
+        [b'*', rest @ ..] => {
+            (0..=path.len()).take_while(|&i| i == 0 || path[i - 1] != b'/')
+                            .any(|i| glob_matches(rest, &path[i..]))
+        }
+        [b'?', rest @ ..] => match path {
+            [c, path @ ..] if *c != b'/' => glob_matches(rest, path),
+            _ => false,
+        },
+        [p, rest @ ..] => match path {
+            [c, path @ ..] if c == p => glob_matches(rest, path),
+            _ => false,
+        },
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn excluded(path: &str, patterns: &[&str]) -> bool {
+        is_excluded(path, &patterns.iter().map(|p| String::from(*p)).collect::<Vec<String>>())
+    }
+
+    #[test]
+    fn instance_paths_stay_inside() {
+        let directory = Path::new("instance");
+        assert_eq!(instance_path(directory, "mods/a.jar").unwrap(), directory.join("mods").join("a.jar"));
+        for outside in ["../x", "/abs", "C:\\x", "C:x", "a/../../b", "mods\\..\\..\\x", ""] {
+            assert!(instance_path(directory, outside).is_err(), "{} was let through", outside);
+        }
+    }
+
+    #[test]
+    fn star_stays_in_one_folder() {
+        assert!(glob_matches(b"*.log", b"latest.log"));
+        assert!(glob_matches(b"config/*.bak", b"config/sodium.bak"));
+        assert!(glob_matches(b"*", b""));
+        assert!(!glob_matches(b"*.log", b"logs/latest.log"));
+        assert!(!glob_matches(b"config/*.bak", b"config/sub/sodium.bak"));
+        assert!(!glob_matches(b"*.log", b"latest.txt"));
+    }
+
+    #[test]
+    fn double_star_crosses_folders() {
+        assert!(glob_matches(b"**/*.bak", b"config/sub/sodium.bak"));
+        assert!(glob_matches(b"**/*.bak", b"sodium.bak"));
+        assert!(glob_matches(b"config/**", b"config/sub/sodium.bak"));
+        assert!(glob_matches(b"**.bak", b"config/sodium.bak"));
+        //**/ only stops where a folder starts
+        assert!(!glob_matches(b"**/odium.bak", b"config/sodium.bak"));
+        assert!(!glob_matches(b"saves/**", b"config/saves/world"));
+    }
+
+    #[test]
+    fn literals_and_question_marks() {
+        assert!(glob_matches(b"options.txt", b"options.txt"));
+        assert!(!glob_matches(b"options.txt", b"options.txt.bak"));
+        assert!(!glob_matches(b"options.txt", b"optionsof.txt"));
+        assert!(glob_matches(b"world?", b"world2"));
+        assert!(!glob_matches(b"world?", b"world"));
+        assert!(!glob_matches(b"a?b", b"a/b"));
+    }
+
+    #[test]
+    fn excluding_a_folder_excludes_what_is_in_it() {
+        assert!(excluded("logs/latest.log", &["logs"]));
+        assert!(excluded("logs/latest.log", &["logs/"]));
+        assert!(excluded("saves/world/level.dat", &["saves/*"]));
+        assert!(excluded("config/sodium.bak", &["options.txt", "config/*.bak"]));
+        assert!(!excluded("config/sodium.json", &["config/*.bak"]));
+        assert!(!excluded("mylogs/latest.log", &["logs"]));
+        assert!(!excluded("options.txt", &[]));
+    }
+}
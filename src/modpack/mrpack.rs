@@ -0,0 +1,286 @@
+use std::collections::{BTreeMap, HashSet};
+use std::io::Write;
+use std::path::{Path, PathBuf};
+use serde::{Serialize, Deserialize};
+use rayon::prelude::*;
+use sha2::Digest;
+use super::super::download::{InstallError, download_and_check};
+use super::super::lockfile;
+use super::super::instance::{self, FileEnv, InstanceManifest, PackFile, PackInfo};
+use super::super::modloader::{self, Side};
+use super::super::modrinth::MODS_DIR;
+use super::super::store;
+use super::{ImportOptions, INSTALLED_FILES, extract_overrides, instance_path, is_excluded, walk_files};
+
+pub const INDEX_FILE: &str = "modrinth.index.json";
+pub const OVERRIDES: &str = "overrides";
+pub const CLIENT_OVERRIDES: &str = "client-overrides";
+pub const SERVER_OVERRIDES: &str = "server-overrides";
+
+//What modrinth.index.json calls each loader, next to what --loader calls it
+pub const LOADER_DEPENDENCIES: &[(&str, &str)] = &[
+    ("fabric-loader", "fabric"),
+    ("quilt-loader", "quilt"),
+    ("forge", "forge"),
+    ("neoforge", "neoforge"),
+];
+
+//Installs the pack's minecraft and loader, then its files and overrides on top. Returns where the
+//instance ended up.
+pub fn import_mrpack(path: &Path, options: &ImportOptions) -> Result<PathBuf, InstallError> {
+    let mut archive = zip::ZipArchive::new(std::fs::File::open(path)?)?;
+    let index: MrpackIndex = serde_json::from_reader(archive.by_name(INDEX_FILE)?)?;
+
+    if index.game != "minecraft" {
+        return Err(InstallError::Unsupported(format!("{} is a pack for {}, not minecraft", path.display(), index.game)));
+    }
+    if index.format_version != 1 {
+        return Err(InstallError::Unsupported(format!("mrpack format {} isn't supported", index.format_version)));
+    }
+
+    let mc_version = index.minecraft_version()?;
+    let loader_build = match index.loader_spec()? {
+        Some(spec) => Some(modloader::resolve_loader(&spec, mc_version)?),
+        None => None,
+    };
+    let name = options.name.clone().unwrap_or_else(|| instance::name_from(&index.name));
+    let (directory, mut manifest) = instance::install(&name, mc_version, loader_build.as_ref(), options.side, options.accept_eula, None)?;
+
+    let files: Vec<&MrpackFile> = index.files.iter()
+                                       .filter(|file| file.wanted(options.side, options.include_optional))
+                                       .collect();
+    let client = reqwest::blocking::Client::new();
+    files.par_iter()
+         .map(|file| install_file(file, &directory, &client))
+         .collect::<Result<Vec<()>, InstallError>>()?;
+
+    //Side specific overrides go on last so they win over the shared ones
+    let side_overrides = match options.side {
+        Side::Client => CLIENT_OVERRIDES,
+        Side::Server => SERVER_OVERRIDES,
+    };
+    extract_overrides(&mut archive, OVERRIDES, &directory)?;
+    extract_overrides(&mut archive, side_overrides, &directory)?;
+
+    manifest.pack = Some(PackInfo {
+        name: index.name.clone(),
+        version: index.version_id.clone(),
+        summary: index.summary.clone(),
+    });
+    manifest.files = files.iter().map(|file| file.to_pack_file()).collect();
+    manifest.save(&directory)?;
+    lockfile::update_mods(&directory, &manifest)?;
+    println!("Imported {} {}", index.name, index.version_id);
+    Ok(directory)
+}
+
+fn install_file(file: &MrpackFile, directory: &Path, client: &reqwest::blocking::Client) -> Result<(), InstallError> {
+    let location = instance_path(directory, &file.path)?;
+    if let Ok(bytes) = std::fs::read(&location) {
+        if sha1::Sha1::from(&bytes).digest().to_string() == file.hashes.sha1 {
+            return Ok(());
+        }
+    }
+
+    //Every download is a mirror of the same file, so the first that works will do
+    let mut last_error = None;
+    for url in &file.downloads {
+        let bytes = match download_and_check(url, Some(client)) {
+            Ok(bytes) => bytes,
+            Err(e) => {
+                last_error = Some(e);
+                continue;
+            }
+        };
+        file.check_hashes(url, &bytes)?;
+
+        if let Some(parent) = location.parent() {
+            std::fs::create_dir_all(parent)?;
+        }
+        store::place(&bytes, &location)?;
+        println!("downloaded {}", file.path);
+        return Ok(());
+    }
+
+    Err(last_error.unwrap_or_else(|| InstallError::Unsupported(format!("{} has nowhere to download it from", file.path))))
+}
+
+#[derive(Debug, Clone, Default)]
+pub struct ExportOptions {
+    //Defaults to the pack the instance came from, or else the instance's folder name
+    pub name: Option<String>,
+    pub version: Option<String>,
+    pub summary: Option<String>,
+    //Patterns for paths to leave out, on top of the files installing puts back anyway
+    pub exclude: Vec<String>,
+}
+
+//Writes the instance out as an mrpack. Anything with a known download goes in the index and
+//everything else goes in overrides.
+pub fn export_mrpack(directory: &Path, output: &Path, options: &ExportOptions) -> Result<MrpackIndex, InstallError> {
+    let manifest = InstanceManifest::load(directory)?;
+
+    let mut dependencies = BTreeMap::new();
+    dependencies.insert(String::from("minecraft"), manifest.minecraft.clone());
+    if let Some(loader) = &manifest.loader {
+        let dependency = LOADER_DEPENDENCIES.iter()
+                                            .find(|(_, name)| *name == loader.name)
+                                            .map(|(dependency, _)| *dependency)
+                                            .ok_or_else(|| InstallError::Unsupported(format!("mrpacks can't have {} as a loader", loader.name)))?;
+        dependencies.insert(String::from(dependency), loader.version.clone());
+    }
+
+    let mut exclude: Vec<String> = INSTALLED_FILES.iter().map(|p| String::from(*p)).collect();
+    exclude.extend(options.exclude.iter().cloned());
+
+    //Files only count as downloadable while they're still what was downloaded
+    let known = manifest.mods.iter()
+                             .map(|m| PackFile {
+                                 path: format!("{}/{}", MODS_DIR, m.file_name),
+                                 downloads: vec![m.url.clone()],
+                                 sha1: String::new(),
+                                 sha512: m.sha512.clone(),
+                                 size: 0,
+                                 env: None,
+                             })
+                             .chain(manifest.files.iter().cloned());
+    let mut files = Vec::new();
+    for known in known {
+        if is_excluded(&known.path, &options.exclude) || files.iter().any(|f: &MrpackFile| f.path == known.path) {
+            continue;
+        }
+        let bytes = match std::fs::read(instance_path(directory, &known.path)?) {
+            Ok(bytes) => bytes,
+            Err(_) => continue,
+        };
+        if format!("{:x}", sha2::Sha512::digest(&bytes)) != known.sha512 {
+            continue;
+        }
+        files.push(MrpackFile {
+            path: known.path,
+            hashes: MrpackHashes {
+                sha1: sha1::Sha1::from(&bytes).digest().to_string(),
+                sha512: known.sha512,
+            },
+            env: known.env,
+            downloads: known.downloads,
+            file_size: bytes.len() as u64,
+        });
+    }
+    let downloadable: HashSet<&str> = files.iter().map(|f| f.path.as_str()).collect();
+
+    let index = MrpackIndex {
+        format_version: 1,
+        game: String::from("minecraft"),
+        version_id: options.version.clone()
+                                   .or_else(|| manifest.pack.as_ref().map(|p| p.version.clone()))
+                                   .unwrap_or_else(|| String::from("1.0.0")),
+        name: options.name.clone()
+                          .or_else(|| manifest.pack.as_ref().map(|p| p.name.clone()))
+                          .or_else(|| directory.file_name().map(|n| n.to_string_lossy().into_owned()))
+                          .unwrap_or_else(|| manifest.minecraft.clone()),
+        summary: options.summary.clone().or_else(|| manifest.pack.as_ref().and_then(|p| p.summary.clone())),
+        files: files.clone(),
+        dependencies,
+    };
+
+    let mut pack = zip::ZipWriter::new(std::fs::File::create(output)?);
+    let zip_options = zip::write::FileOptions::default();
+    pack.start_file(INDEX_FILE, zip_options)?;
+    pack.write_all(serde_json::to_string_pretty(&index)?.as_bytes())?;
+    for path in walk_files(directory)? {
+        if downloadable.contains(path.as_str()) || is_excluded(&path, &exclude) {
+            continue;
+        }
+        pack.start_file(format!("{}/{}", OVERRIDES, path), zip_options)?;
+        pack.write_all(&std::fs::read(directory.join(&path))?)?;
+    }
+    pack.finish()?;
+    Ok(index)
+}
+
+#[derive(Serialize, Deserialize, Debug, Clone)]
+pub struct MrpackIndex {
+    #[serde(rename = "formatVersion")]
+    pub format_version: u32,
+    pub game: String,
+    #[serde(rename = "versionId")]
+    pub version_id: String,
+    pub name: String,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub summary: Option<String>,
+    pub files: Vec<MrpackFile>,
+    pub dependencies: BTreeMap<String, String>,
+}
+
+impl MrpackIndex {
+    pub fn minecraft_version(&self) -> Result<&str, InstallError> {
+        self.dependencies.get("minecraft")
+                         .map(|v| v.as_str())
+                         .ok_or_else(|| InstallError::Unsupported(format!("{} doesn't say which minecraft it's for", self.name)))
+    }
+
+    //The pack's loader as name@build, ready for resolve_loader
+    pub fn loader_spec(&self) -> Result<Option<String>, InstallError> {
+        let mut specs = LOADER_DEPENDENCIES.iter()
+                                           .filter_map(|(dependency, name)| {
+                                               self.dependencies.get(*dependency).map(|build| format!("{}@{}", name, build))
+                                           });
+        let spec = specs.next();
+        if specs.next().is_some() {
+            return Err(InstallError::Unsupported(format!("{} depends on more than one modloader", self.name)));
+        }
+        Ok(spec)
+    }
+}
+
+#[derive(Serialize, Deserialize, Debug, Clone)]
+pub struct MrpackFile {
+    pub path: String,
+    pub hashes: MrpackHashes,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub env: Option<FileEnv>,
+    pub downloads: Vec<String>,
+    #[serde(rename = "fileSize")]
+    pub file_size: u64,
+}
+
+impl MrpackFile {
+    //Files without an env are needed everywhere
+    pub fn wanted(&self, side: Side, include_optional: bool) -> bool {
+        match self.env.as_ref().map(|env| env.for_side(side)) {
+            Some("unsupported") => false,
+            Some("optional") => include_optional,
+            _ => true,
+        }
+    }
+
+    fn check_hashes(&self, url: &str, bytes: &[u8]) -> Result<(), InstallError> {
+        let sha1 = sha1::Sha1::from(bytes).digest().to_string();
+        if sha1 != self.hashes.sha1 {
+            return Err(InstallError::HashError(format!("{} has sha1 {}, expected {}", url, sha1, self.hashes.sha1)));
+        }
+        let sha512 = format!("{:x}", sha2::Sha512::digest(bytes));
+        if sha512 != self.hashes.sha512 {
+            return Err(InstallError::HashError(format!("{} has sha512 {}, expected {}", url, sha512, self.hashes.sha512)));
+        }
+        Ok(())
+    }
+
+    pub fn to_pack_file(&self) -> PackFile {
+        PackFile {
+            path: self.path.clone(),
+            downloads: self.downloads.clone(),
+            sha1: self.hashes.sha1.clone(),
+            sha512: self.hashes.sha512.clone(),
+            size: self.file_size,
+            env: self.env.clone(),
+        }
+    }
+}
+
+#[derive(Serialize, Deserialize, Debug, Clone)]
+pub struct MrpackHashes {
+    pub sha1: String,
+    pub sha512: String,
+}
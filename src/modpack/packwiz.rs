@@ -0,0 +1,561 @@
+//packwiz packs are a tree of toml files: pack.toml points at an index, and the index lists every
+//file with its hash. Mods are metafiles (*.pw.toml) that say where to download the jar from.
+use std::collections::{BTreeMap, HashSet};
+use std::path::{Path, PathBuf};
+use serde::{Serialize, Deserialize};
+use rayon::prelude::*;
+use sha2::Digest;
+use super::super::download::{InstallError, download_and_check};
+use super::super::lockfile;
+use super::super::instance::{self, FileEnv, PackFile, PackInfo, PackwizEntry, PackwizState};
+use super::super::modloader::{self, Side};
+use super::super::store;
+use super::curseforge::ManualDownload;
+use super::{ImportOptions, instance_path};
+
+pub const PACK_FILE: &str = "pack.toml";
+const METAFILE_SUFFIX: &str = ".pw.toml";
+//The [versions] keys packwiz uses for loaders, which happen to be what --loader calls them
+const LOADER_VERSIONS: &[&str] = &["fabric", "quilt", "forge", "neoforge"];
+
+//Where a pack is read from: a folder on disk, i.e. a git checkout, or wherever it's hosted
+#[derive(Debug, Clone)]
+pub enum PackSource {
+    Directory(PathBuf),
+    Url(reqwest::Url),
+}
+
+impl PackSource {
+    //Takes a pack folder, a pack.toml, or a URL to a pack.toml. Returns the source along with
+    //pack.toml's path in it.
+    pub fn from_location(location: &str) -> Result<(PackSource, String), InstallError> {
+        if location.starts_with("http://") || location.starts_with("https://") {
+            let url = reqwest::Url::parse(location)?;
+            let pack_file = url.path_segments()
+                               .and_then(|mut segments| segments.next_back())
+                               .filter(|name| !name.is_empty())
+                               .unwrap_or(PACK_FILE)
+                               .to_string();
+            return Ok((PackSource::Url(url.join(".")?), pack_file));
+        }
+
+        let path = Path::new(location);
+        if path.is_dir() {
+            return Ok((PackSource::Directory(path.to_path_buf()), String::from(PACK_FILE)));
+        }
+        let pack_file = path.file_name()
+                            .map(|name| name.to_string_lossy().into_owned())
+                            .ok_or_else(|| InstallError::Unsupported(format!("{} isn't a packwiz pack", location)))?;
+        let directory = path.parent().map(Path::to_path_buf).unwrap_or_default();
+        Ok((PackSource::Directory(directory), pack_file))
+    }
+
+    fn read(&self, path: &str, client: &reqwest::blocking::Client) -> Result<Vec<u8>, InstallError> {
+        match self {
+            PackSource::Directory(directory) => Ok(std::fs::read(directory.join(path))?),
+            PackSource::Url(base) => download_and_check(&base.join(path)?.to_string(), Some(client)),
+        }
+    }
+}
+
+impl std::fmt::Display for PackSource {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        match self {
+            PackSource::Directory(directory) => write!(f, "{}", directory.display()),
+            PackSource::Url(url) => write!(f, "{}", url),
+        }
+    }
+}
+
+pub fn is_packwiz(location: &str) -> bool {
+    if location.starts_with("http://") || location.starts_with("https://") {
+        return location.ends_with(".toml");
+    }
+    let path = Path::new(location);
+    path.join(PACK_FILE).is_file() || path.file_name().map(|name| name == PACK_FILE).unwrap_or(false)
+}
+
+//Installs the pack's minecraft and loader, then every file in its index. Running it again on the
+//same instance only fetches what changed in the index and removes what's gone from it.
+pub fn import_packwiz(location: &str, options: &ImportOptions) -> Result<(PathBuf, Vec<ManualDownload>), InstallError> {
+    let client = reqwest::blocking::Client::new();
+    let (source, pack_file) = PackSource::from_location(location)?;
+    let pack: PackToml = toml::from_slice(&source.read(&pack_file, &client)?)?;
+
+    let index_file = join_relative(parent_of(&pack_file), &pack.index.file);
+    let index_bytes = source.read(&index_file, &client)?;
+    check_hash(&index_file, &pack.index.hash_format, &pack.index.hash, &index_bytes)?;
+    let index: IndexToml = toml::from_slice(&index_bytes)?;
+
+    let mc_version = pack.versions.get("minecraft").ok_or_else(|| {
+        InstallError::Unsupported(format!("{} doesn't say which minecraft it's for", pack.name))
+    })?;
+    let mut loaders = LOADER_VERSIONS.iter()
+                                     .filter_map(|name| pack.versions.get(*name).map(|build| format!("{}@{}", name, build)));
+    let loader_spec = loaders.next();
+    if loaders.next().is_some() {
+        return Err(InstallError::Unsupported(format!("{} depends on more than one modloader", pack.name)));
+    }
+    let loader_build = match loader_spec {
+        Some(spec) => Some(modloader::resolve_loader(&spec, mc_version)?),
+        None => None,
+    };
+    let name = options.name.clone().unwrap_or_else(|| instance::name_from(&pack.name));
+    let (directory, mut manifest) = instance::install(&name, mc_version, loader_build.as_ref(), options.side, options.accept_eula, None)?;
+
+    //Only an earlier install of the same pack counts for updating
+    let state = manifest.packwiz.take().filter(|state| state.source == source.to_string());
+    let reusable = state.as_ref().map(|state| can_reuse(state, options)).unwrap_or(false);
+    let previous = state.map(|state| state.entries).unwrap_or_default();
+
+    let index_dir = parent_of(&index_file);
+    let outcomes = index.files.par_iter()
+                        .map(|file| {
+                            let hash_format = file.hash_format.as_deref().unwrap_or(&index.hash_format);
+                            install_entry(file, hash_format, index_dir, &source, &directory, previous.get(&file.file).filter(|_| reusable), options, &client)
+                        })
+                        .collect::<Result<Vec<Outcome>, InstallError>>()?;
+
+    let mut entries = BTreeMap::new();
+    let mut files = Vec::new();
+    let mut manual = Vec::new();
+    for (file, outcome) in index.files.iter().zip(outcomes) {
+        match outcome {
+            Outcome::Installed(entry, pack_file) => {
+                files.extend(pack_file);
+                entries.insert(file.file.clone(), entry);
+            }
+            Outcome::Unchanged(entry) => {
+                files.extend(manifest.files.iter().find(|f| f.path == entry.path).cloned());
+                entries.insert(file.file.clone(), entry);
+            }
+            Outcome::Manual(download) => manual.push(download),
+            Outcome::Skipped => (),
+        }
+    }
+
+    //Whatever the last install put down that this one didn't is gone from the pack
+    let kept: HashSet<&str> = entries.values().map(|e| e.path.as_str()).collect();
+    for (_, old) in previous.iter().filter(|(_, old)| !kept.contains(old.path.as_str())) {
+        match std::fs::remove_file(instance_path(&directory, &old.path)?) {
+            Ok(()) => println!("removed {}", old.path),
+            Err(e) if e.kind() == std::io::ErrorKind::NotFound => (),
+            Err(e) => return Err(e.into()),
+        }
+    }
+
+    manifest.pack = Some(PackInfo {
+        name: pack.name.clone(),
+        version: pack.version.clone().unwrap_or_default(),
+        summary: pack.description.clone(),
+    });
+    manifest.files = files;
+    manifest.packwiz = Some(PackwizState {
+        source: source.to_string(),
+        index_hash: pack.index.hash.clone(),
+        side: Some(options.side),
+        include_optional: options.include_optional,
+        entries,
+    });
+    manifest.save(&directory)?;
+    lockfile::update_mods(&directory, &manifest)?;
+    println!("Imported {} {}", pack.name, pack.version.as_deref().unwrap_or(""));
+    Ok((directory, manual))
+}
+
+//Whether a metafile is wanted depends on what the import asks for, so when that's changed
+//nothing from the last import can be taken as unchanged. What's no longer wanted gets removed.
+fn can_reuse(state: &PackwizState, options: &ImportOptions) -> bool {
+    state.side == Some(options.side) && state.include_optional == options.include_optional
+}
+
+enum Outcome {
+    Installed(PackwizEntry, Option<PackFile>),
+    Unchanged(PackwizEntry),
+    Manual(ManualDownload),
+    //Not for this side, or optional and not asked for
+    Skipped,
+}
+
+#[allow(clippy::too_many_arguments)]
+fn install_entry(file: &IndexFile, hash_format: &str, index_dir: &str, source: &PackSource, directory: &Path,
+                 previous: Option<&PackwizEntry>, options: &ImportOptions, client: &reqwest::blocking::Client) -> Result<Outcome, InstallError> {
+    if let Some(previous) = previous {
+        if previous.hash.eq_ignore_ascii_case(&file.hash) && instance_path(directory, &previous.path)?.exists() {
+            return Ok(Outcome::Unchanged(previous.clone()));
+        }
+    }
+
+    let source_path = join_relative(index_dir, &file.file);
+    let bytes = source.read(&source_path, client)?;
+    check_hash(&source_path, hash_format, &file.hash, &bytes)?;
+
+    if !file.is_metafile() {
+        let path = file.alias.clone().unwrap_or_else(|| file.file.clone());
+        let location = instance_path(directory, &path)?;
+        //Preserved files are defaults the player is expected to change
+        if !(file.preserve && location.exists()) {
+            if let Some(parent) = location.parent() {
+                std::fs::create_dir_all(parent)?;
+            }
+            store::unlink(&location)?;
+            std::fs::write(&location, &bytes)?;
+            println!("wrote {}", path);
+        }
+        return Ok(Outcome::Installed(PackwizEntry { hash: file.hash.clone(), path }, None));
+    }
+
+    let meta: MetaFile = toml::from_slice(&bytes)?;
+    if !meta.wanted(options.side, options.include_optional) {
+        return Ok(Outcome::Skipped);
+    }
+    let path = join_relative(parent_of(&file.file), &meta.filename);
+    let location = instance_path(directory, &path)?;
+
+    let url = match &meta.download.url {
+        Some(url) => url,
+        //CurseForge mods that can't be downloaded elsewhere only have their ids
+        None => {
+            let curseforge = meta.update.as_ref().and_then(|update| update.curseforge.as_ref());
+            return Ok(Outcome::Manual(ManualDownload {
+                name: meta.name.clone(),
+                file_name: meta.filename.clone(),
+                url: curseforge.map(|cf| format!("https://www.curseforge.com/projects/{}/files/{}", cf.project_id, cf.file_id))
+                               .unwrap_or_default(),
+                path: location,
+            }));
+        }
+    };
+
+    let bytes = match std::fs::read(&location) {
+        Ok(bytes) if hash_bytes(&meta.download.hash_format, &bytes)?.eq_ignore_ascii_case(&meta.download.hash) => bytes,
+        _ => {
+            let bytes = download_and_check(url, Some(client))?;
+            check_hash(url, &meta.download.hash_format, &meta.download.hash, &bytes)?;
+            if let Some(parent) = location.parent() {
+                std::fs::create_dir_all(parent)?;
+            }
+            store::place(&bytes, &location)?;
+            println!("downloaded {}", path);
+            bytes
+        }
+    };
+
+    let pack_file = PackFile {
+        path: path.clone(),
+        downloads: vec![url.clone()],
+        sha1: sha1::Sha1::from(&bytes).digest().to_string(),
+        sha512: format!("{:x}", sha2::Sha512::digest(&bytes)),
+        size: bytes.len() as u64,
+        env: meta.env(),
+    };
+    Ok(Outcome::Installed(PackwizEntry { hash: file.hash.clone(), path }, Some(pack_file)))
+}
+
+fn check_hash(name: &str, format: &str, expected: &str, bytes: &[u8]) -> Result<(), InstallError> {
+    let actual = hash_bytes(format, bytes)?;
+    if !actual.eq_ignore_ascii_case(expected) {
+        return Err(InstallError::HashError(format!("{} has {} {}, expected {}", name, format, actual, expected)));
+    }
+    Ok(())
+}
+
+//Hex for the sha family, and a decimal number for murmur2 the way CurseForge writes it
+pub fn hash_bytes(format: &str, bytes: &[u8]) -> Result<String, InstallError> {
+    match format {
+        "sha1" => Ok(sha1::Sha1::from(bytes).digest().to_string()),
+        "sha256" => Ok(format!("{:x}", sha2::Sha256::digest(bytes))),
+        "sha512" => Ok(format!("{:x}", sha2::Sha512::digest(bytes))),
+        "murmur2" => Ok(murmur2(bytes).to_string()),
+        format => Err(InstallError::Unsupported(format!("{} hashes aren't supported", format))),
+    }
+}
+
+//CurseForge's fingerprint: 32 bit murmur2 with a seed of 1, over the file without any whitespace
+fn murmur2(bytes: &[u8]) -> u32 {
+    const M: u32 = 0x5bd1e995;
+    let data: Vec<u8> = bytes.iter()
+                             .copied()
+                             .filter(|b| !matches!(b, 9 | 10 | 13 | 32))
+                             .collect();
+
+    let mut h = 1 ^ data.len() as u32;
+    let mut chunks = data.chunks_exact(4);
+    for chunk in &mut chunks {
+        let mut k = u32::from_le_bytes([chunk[0], chunk[1], chunk[2], chunk[3]]);
+        k = k.wrapping_mul(M);
+        k ^= k >> 24;
+        k = k.wrapping_mul(M);
+        h = h.wrapping_mul(M) ^ k;
+    }
+    let rest = chunks.remainder();
+    if !rest.is_empty() {
+        for (i, b) in rest.iter().enumerate() {
+            h ^= (*b as u32) << (8 * i);
+        }
+        h = h.wrapping_mul(M);
+    }
+
+    h ^= h >> 13;
+    h = h.wrapping_mul(M);
+    h ^ (h >> 15)
+}
+
+fn parent_of(path: &str) -> &str {
+    path.rfind('/').map(|slash| &path[..slash]).unwrap_or("")
+}
+
+fn join_relative(directory: &str, path: &str) -> String {
+    if directory.is_empty() {
+        String::from(path)
+    } else {
+        format!("{}/{}", directory, path)
+    }
+}
+
+#[derive(Serialize, Deserialize, Debug, Clone)]
+pub struct PackToml {
+    pub name: String,
+    pub version: Option<String>,
+    pub description: Option<String>,
+    #[serde(rename = "pack-format")]
+    pub pack_format: Option<String>,
+    pub index: PackIndexRef,
+    //minecraft, and the loader's version under its name
+    pub versions: BTreeMap<String, String>,
+}
+
+#[derive(Serialize, Deserialize, Debug, Clone)]
+pub struct PackIndexRef {
+    pub file: String,
+    #[serde(rename = "hash-format")]
+    pub hash_format: String,
+    pub hash: String,
+}
+
+#[derive(Serialize, Deserialize, Debug, Clone)]
+pub struct IndexToml {
+    #[serde(rename = "hash-format")]
+    pub hash_format: String,
+    #[serde(default)]
+    pub files: Vec<IndexFile>,
+}
+
+#[derive(Serialize, Deserialize, Debug, Clone)]
+pub struct IndexFile {
+    pub file: String,
+    pub hash: String,
+    #[serde(rename = "hash-format")]
+    pub hash_format: Option<String>,
+    pub alias: Option<String>,
+    #[serde(default)]
+    pub metafile: bool,
+    #[serde(default)]
+    pub preserve: bool,
+}
+
+impl IndexFile {
+    //Older indexes don't always mark metafiles, but the name gives them away
+    fn is_metafile(&self) -> bool {
+        self.metafile || self.file.ends_with(METAFILE_SUFFIX)
+    }
+}
+
+#[derive(Serialize, Deserialize, Debug, Clone)]
+pub struct MetaFile {
+    pub name: String,
+    pub filename: String,
+    //client, server or both
+    pub side: Option<String>,
+    pub download: MetaDownload,
+    pub option: Option<MetaOption>,
+    pub update: Option<MetaUpdate>,
+}
+
+impl MetaFile {
+    fn wanted(&self, side: Side, include_optional: bool) -> bool {
+        let for_side = match self.side.as_deref() {
+            Some("client") => side == Side::Client,
+            Some("server") => side == Side::Server,
+            _ => true,
+        };
+        let chosen = match &self.option {
+            Some(option) if option.optional => include_optional || option.default,
+            _ => true,
+        };
+        for_side && chosen
+    }
+
+    fn env(&self) -> Option<FileEnv> {
+        let optional = self.option.as_ref().map(|o| o.optional).unwrap_or(false);
+        let wanted = if optional { "optional" } else { "required" };
+        match self.side.as_deref() {
+            Some("client") => Some(FileEnv { client: String::from(wanted), server: String::from("unsupported") }),
+            Some("server") => Some(FileEnv { client: String::from("unsupported"), server: String::from(wanted) }),
+            _ if optional => Some(FileEnv { client: String::from(wanted), server: String::from(wanted) }),
+            _ => None,
+        }
+    }
+}
+
+#[derive(Serialize, Deserialize, Debug, Clone)]
+pub struct MetaDownload {
+    pub url: Option<String>,
+    #[serde(rename = "hash-format")]
+    pub hash_format: String,
+    pub hash: String,
+}
+
+#[derive(Serialize, Deserialize, Debug, Clone)]
+pub struct MetaOption {
+    #[serde(default)]
+    pub optional: bool,
+    #[serde(default)]
+    pub default: bool,
+    pub description: Option<String>,
+}
+
+#[derive(Serialize, Deserialize, Debug, Clone)]
+pub struct MetaUpdate {
+    pub curseforge: Option<MetaCurseForge>,
+}
+
+#[derive(Serialize, Deserialize, Debug, Clone)]
+pub struct MetaCurseForge {
+    #[serde(rename = "file-id")]
+    pub file_id: u32,
+    #[serde(rename = "project-id")]
+    pub project_id: u32,
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    //Worked out with a Python port of Austin Appleby's MurmurHash2 run with a seed of 1. With Kafka's
+    //seed of 0x9747b28c the same port gives the test vectors for Apache Kafka's Utils.murmur2.
+    #[test]
+    fn murmur2_known_answers() {
+        assert_eq!(murmur2(b""), 1540447798);
+        assert_eq!(murmur2(b"a"), 626045324);
+        assert_eq!(murmur2(b"ab"), 1692487918);
+        assert_eq!(murmur2(b"abc"), 1621425345);
+        assert_eq!(murmur2(b"abcd"), 3376380438);
+        assert_eq!(murmur2(b"helloworld"), 2824650221);
+    }
+
+    #[test]
+    fn murmur2_skips_whitespace() {
+        assert_eq!(murmur2(b"hello world"), murmur2(b"helloworld"));
+        assert_eq!(murmur2(b"hello\r\n\tworld "), 2824650221);
+        assert_eq!(murmur2(b" \t\r\n"), murmur2(b""));
+    }
+
+    #[test]
+    fn hash_bytes_formats() {
+        assert_eq!(hash_bytes("murmur2", b"abcd").unwrap(), "3376380438");
+        assert_eq!(hash_bytes("sha1", b"abc").unwrap(), "a9993e364706816aba3e25717850c26c9cd0d89d");
+        assert!(hash_bytes("md5", b"abc").is_err());
+    }
+
+    fn meta(side: Option<&str>, option: Option<MetaOption>) -> MetaFile {
+        MetaFile {
+            name: String::from("Sodium"),
+            filename: String::from("sodium.jar"),
+            side: side.map(String::from),
+            download: MetaDownload { url: None, hash_format: String::from("sha1"), hash: String::new() },
+            option,
+            update: None,
+        }
+    }
+
+    #[test]
+    fn sided_entries_are_skipped_for_the_other_side() {
+        let client_only = meta(Some("client"), None);
+        assert!(client_only.wanted(Side::Client, false));
+        assert!(!client_only.wanted(Side::Server, false));
+        assert_eq!(client_only.env().map(|env| (env.client, env.server)),
+                   Some((String::from("required"), String::from("unsupported"))));
+
+        let server_only = meta(Some("server"), None);
+        assert!(!server_only.wanted(Side::Client, true));
+        assert!(server_only.wanted(Side::Server, false));
+
+        let both = meta(Some("both"), None);
+        assert!(both.wanted(Side::Client, false) && both.wanted(Side::Server, false));
+        assert!(both.env().is_none());
+    }
+
+    #[test]
+    fn optional_entries_need_asking_for_unless_on_by_default() {
+        let optional = meta(None, Some(MetaOption { optional: true, default: false, description: None }));
+        assert!(!optional.wanted(Side::Client, false));
+        assert!(optional.wanted(Side::Client, true));
+        assert_eq!(optional.env().map(|env| (env.client, env.server)),
+                   Some((String::from("optional"), String::from("optional"))));
+
+        let on_by_default = meta(None, Some(MetaOption { optional: true, default: true, description: None }));
+        assert!(on_by_default.wanted(Side::Client, false));
+    }
+
+    #[test]
+    fn changed_import_options_refetch_everything() {
+        let state = PackwizState {
+            source: String::from("pack"),
+            index_hash: String::new(),
+            side: Some(Side::Client),
+            include_optional: false,
+            entries: BTreeMap::new(),
+        };
+        let options = |side, include_optional| ImportOptions { side, include_optional, accept_eula: false, name: None };
+        assert!(can_reuse(&state, &options(Side::Client, false)));
+        assert!(!can_reuse(&state, &options(Side::Client, true)));
+        assert!(!can_reuse(&state, &options(Side::Server, false)));
+        //Imports from before these were recorded can't say what they asked for
+        assert!(!can_reuse(&PackwizState { side: None, ..state }, &options(Side::Client, false)));
+    }
+
+    #[test]
+    fn joins_paths_inside_the_pack() {
+        assert_eq!(parent_of("pack.toml"), "");
+        assert_eq!(parent_of("packs/main/pack.toml"), "packs/main");
+        assert_eq!(join_relative("", "index.toml"), "index.toml");
+        assert_eq!(join_relative("packs/main", "mods/sodium.pw.toml"), "packs/main/mods/sodium.pw.toml");
+        assert_eq!(join_relative(parent_of("mods/sodium.pw.toml"), "sodium.jar"), "mods/sodium.jar");
+    }
+
+    #[test]
+    fn pack_locations() {
+        match PackSource::from_location("https://example.com/packs/main/pack.toml").unwrap() {
+            (PackSource::Url(url), pack_file) => {
+                assert_eq!(url.as_str(), "https://example.com/packs/main/");
+                assert_eq!(pack_file, "pack.toml");
+            }
+            other => panic!("{:?}", other),
+        }
+        match PackSource::from_location("https://example.com/packs/main/").unwrap() {
+            (PackSource::Url(url), pack_file) => {
+                assert_eq!(url.as_str(), "https://example.com/packs/main/");
+                assert_eq!(pack_file, PACK_FILE);
+            }
+            other => panic!("{:?}", other),
+        }
+
+        match PackSource::from_location("some/pack/beta.toml").unwrap() {
+            (PackSource::Directory(directory), pack_file) => {
+                assert_eq!(directory, Path::new("some/pack"));
+                assert_eq!(pack_file, "beta.toml");
+            }
+            other => panic!("{:?}", other),
+        }
+
+        let folder = std::env::temp_dir();
+        match PackSource::from_location(&folder.to_string_lossy()).unwrap() {
+            (PackSource::Directory(directory), pack_file) => {
+                assert_eq!(directory, folder);
+                assert_eq!(pack_file, PACK_FILE);
+            }
+            other => panic!("{:?}", other),
+        }
+    }
+}
@@ -0,0 +1,346 @@
+use std::collections::BTreeMap;
+use std::io::Write;
+use std::path::{Path, PathBuf};
+use serde::{Serialize, Deserialize};
+use super::super::download::InstallError;
+use super::super::instance::{self, InstanceManifest, JavaSettings};
+use super::super::modloader;
+use super::super::store;
+use super::mrpack::ExportOptions;
+use super::{ImportOptions, INSTALLED_FILES, extract_overrides, is_excluded, walk_files};
+
+pub const PACK_FILE: &str = "mmc-pack.json";
+pub const CONFIG_FILE: &str = "instance.cfg";
+//Prism calls the game folder .minecraft, older MultiMC instances call it minecraft
+pub const GAME_DIRS: &[&str] = &[".minecraft", "minecraft"];
+
+const MINECRAFT_UID: &str = "net.minecraft";
+//Prism won't launch loaders that use intermediary without it
+const INTERMEDIARY_UID: &str = "net.fabricmc.intermediary";
+
+//Prism instances are folders, or zips of one when they're exported. Zips are found by
+//detect_format.
+pub fn is_prism(path: &Path) -> bool {
+    path.join(PACK_FILE).is_file()
+}
+
+//Exported zips have the files either at the top or inside a single folder
+pub fn zip_root<R: std::io::Read + std::io::Seek>(archive: &zip::ZipArchive<R>) -> Option<String> {
+    archive.file_names()
+           .find(|name| *name == PACK_FILE || (name.ends_with(&format!("/{}", PACK_FILE)) && name.matches('/').count() == 1))
+           .map(|name| String::from(name.trim_end_matches(PACK_FILE)))
+}
+
+//Installs the instance's minecraft and loader, then copies its game folder over the top.
+//Returns where the instance ended up.
+pub fn import_prism(path: &Path, options: &ImportOptions) -> Result<PathBuf, InstallError> {
+    let mut archive = None;
+    let (pack, config): (MmcPack, String) = if path.is_dir() {
+        (serde_json::from_slice(&std::fs::read(path.join(PACK_FILE))?)?,
+         std::fs::read_to_string(path.join(CONFIG_FILE)).unwrap_or_default())
+    } else {
+        let mut zip = zip::ZipArchive::new(std::fs::File::open(path)?)?;
+        let root = zip_root(&zip).ok_or_else(|| {
+            InstallError::Unsupported(format!("{} has no {}", path.display(), PACK_FILE))
+        })?;
+        let pack = serde_json::from_reader(zip.by_name(&format!("{}{}", root, PACK_FILE))?)?;
+        let mut config = String::new();
+        if let Ok(mut entry) = zip.by_name(&format!("{}{}", root, CONFIG_FILE)) {
+            std::io::Read::read_to_string(&mut entry, &mut config)?;
+        }
+        archive = Some((zip, root));
+        (pack, config)
+    };
+    let config = parse_config(&config);
+
+    let mc_version = pack.minecraft_version()?;
+    let loader_build = match pack.loader_spec()? {
+        Some(spec) => Some(modloader::resolve_loader(&spec, mc_version)?),
+        None => None,
+    };
+    let name = options.name.clone()
+                           .or_else(|| config.get("name").map(|name| instance::name_from(name)))
+                           .or_else(|| path.file_stem().map(|stem| instance::name_from(&stem.to_string_lossy())))
+                           .unwrap_or_else(|| String::from(mc_version));
+    let (directory, _) = instance::install(&name, mc_version, loader_build.as_ref(), options.side, options.accept_eula, Some(java_settings(&config)))?;
+
+    let copied = match archive {
+        Some((mut zip, root)) => {
+            let mut copied = 0;
+            for game_dir in GAME_DIRS {
+                copied += extract_overrides(&mut zip, &format!("{}{}", root, game_dir), &directory)?;
+            }
+            copied
+        }
+        None => {
+            let mut copied = 0;
+            for game_dir in GAME_DIRS.iter().map(|d| path.join(d)).filter(|d| d.is_dir()) {
+                for file in walk_files(&game_dir)? {
+                    let location = directory.join(&file);
+                    if let Some(parent) = location.parent() {
+                        std::fs::create_dir_all(parent)?;
+                    }
+                    store::unlink(&location)?;
+                    std::fs::copy(game_dir.join(&file), location)?;
+                    copied += 1;
+                }
+            }
+            copied
+        }
+    };
+
+    println!("Imported {} with {} files", name, copied);
+    Ok(directory)
+}
+
+//instance.cfg is an ini file, but everything we care about is in [General]
+fn parse_config(text: &str) -> BTreeMap<String, String> {
+    text.lines()
+        .map(|line| line.trim())
+        .filter(|line| !line.starts_with('[') && !line.starts_with('#') && !line.starts_with(';'))
+        .filter_map(|line| {
+            let mut parts = line.splitn(2, '=');
+            let key = parts.next()?.trim();
+            let value = parts.next()?.trim().trim_matches('"');
+            Some((String::from(key), String::from(value)))
+        })
+        .collect()
+}
+
+//Prism keeps the global java settings unless the instance overrides them, so only overrides count
+fn java_settings(config: &BTreeMap<String, String>) -> JavaSettings {
+    let overridden = |key: &str| config.get(key).map(|v| v == "true").unwrap_or(false);
+    let value = |key: &str| config.get(key).filter(|v| !v.is_empty()).cloned();
+    JavaSettings {
+        path: if overridden("OverrideJavaLocation") || overridden("OverrideJava") { value("JavaPath") } else { None },
+        memory: if overridden("OverrideMemory") { value("MaxMemAlloc").map(|mb| format!("{}M", mb)) } else { None },
+        args: if overridden("OverrideJavaArgs") {
+            value("JvmArgs").map(|args| args.split_whitespace().map(String::from).collect()).unwrap_or_default()
+        } else {
+            Vec::new()
+        },
+    }
+}
+
+//Prism wants megabytes, we take whatever java's -Xmx takes
+fn memory_megabytes(memory: &str) -> Option<u64> {
+    let memory = memory.trim();
+    let (number, scale) = match memory.chars().last()? {
+        'g' | 'G' => (&memory[..memory.len() - 1], 1024),
+        'm' | 'M' => (&memory[..memory.len() - 1], 1),
+        'k' | 'K' => return memory[..memory.len() - 1].parse::<u64>().ok().map(|k| k / 1024),
+        _ => (memory, 1),
+    };
+    number.parse::<u64>().ok().map(|n| n * scale)
+}
+
+//Writes the instance out as a zip Prism and MultiMC can import, with the instance folder's
+//contents in .minecraft
+pub fn export_prism(directory: &Path, output: &Path, options: &ExportOptions) -> Result<MmcPack, InstallError> {
+    let manifest = InstanceManifest::load(directory)?;
+    let pack = MmcPack::for_manifest(&manifest)?;
+    let name = options.name.clone().unwrap_or_else(|| manifest.name.clone());
+
+    let mut config = vec![
+        String::from("[General]"),
+        String::from("ConfigVersion=1.2"),
+        String::from("InstanceType=OneSix"),
+        format!("name={}", name),
+    ];
+    if let Some(path) = &manifest.java.path {
+        config.push(String::from("OverrideJavaLocation=true"));
+        config.push(format!("JavaPath={}", path));
+    }
+    if let Some(memory) = manifest.java.memory.as_deref().and_then(memory_megabytes) {
+        config.push(String::from("OverrideMemory=true"));
+        config.push(format!("MaxMemAlloc={}", memory));
+    }
+    if !manifest.java.args.is_empty() {
+        config.push(String::from("OverrideJavaArgs=true"));
+        config.push(format!("JvmArgs={}", manifest.java.args.join(" ")));
+    }
+
+    let mut exclude: Vec<String> = INSTALLED_FILES.iter().map(|p| String::from(*p)).collect();
+    exclude.extend(options.exclude.iter().cloned());
+
+    let mut zip = zip::ZipWriter::new(std::fs::File::create(output)?);
+    let zip_options = zip::write::FileOptions::default();
+    zip.start_file(CONFIG_FILE, zip_options)?;
+    zip.write_all(format!("{}\n", config.join("\n")).as_bytes())?;
+    zip.start_file(PACK_FILE, zip_options)?;
+    zip.write_all(serde_json::to_string_pretty(&pack)?.as_bytes())?;
+    for path in walk_files(directory)? {
+        if is_excluded(&path, &exclude) {
+            continue;
+        }
+        zip.start_file(format!("{}/{}", GAME_DIRS[0], path), zip_options)?;
+        zip.write_all(&std::fs::read(directory.join(&path))?)?;
+    }
+    zip.finish()?;
+    Ok(pack)
+}
+
+#[derive(Serialize, Deserialize, Debug, Clone)]
+pub struct MmcPack {
+    pub components: Vec<PackComponent>,
+    #[serde(rename = "formatVersion")]
+    pub format_version: u32,
+}
+
+#[derive(Serialize, Deserialize, Debug, Clone)]
+pub struct PackComponent {
+    pub uid: String,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub version: Option<String>,
+    //Older packs only have the version Prism last resolved
+    #[serde(rename = "cachedVersion", default, skip_serializing_if = "Option::is_none")]
+    pub cached_version: Option<String>,
+    #[serde(default, skip_serializing_if = "std::ops::Not::not")]
+    pub important: bool,
+}
+
+impl PackComponent {
+    fn new(uid: &str, version: &str) -> Self {
+        PackComponent {
+            uid: String::from(uid),
+            version: Some(String::from(version)),
+            cached_version: None,
+            important: false,
+        }
+    }
+
+    pub fn version(&self) -> Option<&str> {
+        self.version.as_deref().or(self.cached_version.as_deref())
+    }
+}
+
+impl MmcPack {
+    pub fn for_manifest(manifest: &InstanceManifest) -> Result<Self, InstallError> {
+        let mut components = vec![PackComponent { important: true, ..PackComponent::new(MINECRAFT_UID, &manifest.minecraft) }];
+        if let Some(loader) = &manifest.loader {
+            let modloader = modloader::get_loader(&loader.name).ok_or_else(|| {
+                InstallError::Unsupported(format!("Prism doesn't know {} as a loader", loader.name))
+            })?;
+            if modloader.uses_intermediary() {
+                components.push(PackComponent::new(INTERMEDIARY_UID, &manifest.minecraft));
+            }
+            components.push(PackComponent::new(modloader.prism_uid(), &loader.version));
+        }
+        Ok(MmcPack { components, format_version: 1 })
+    }
+
+    pub fn minecraft_version(&self) -> Result<&str, InstallError> {
+        self.components.iter()
+                       .find(|c| c.uid == MINECRAFT_UID)
+                       .and_then(|c| c.version())
+                       .ok_or_else(|| InstallError::Unsupported(format!("{} doesn't say which minecraft it's for", PACK_FILE)))
+    }
+
+    //The instance's loader as name@build, ready for resolve_loader
+    pub fn loader_spec(&self) -> Result<Option<String>, InstallError> {
+        let loaders = modloader::loaders();
+        let mut specs = self.components.iter()
+                                       .filter_map(|c| {
+                                           loaders.iter()
+                                                  .find(|loader| loader.prism_uid() == c.uid)
+                                                  .map(|loader| format!("{}@{}", loader.name(), c.version().unwrap_or("")))
+                                       });
+        let spec = specs.next();
+        if specs.next().is_some() {
+            return Err(InstallError::Unsupported(String::from("the instance has more than one modloader")));
+        }
+        Ok(spec)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    const CONFIG: &str = "[General]
+ConfigVersion=1.2
+name=\"Survival\"
+JavaPath=/usr/lib/jvm/java-17/bin/java
+MaxMemAlloc=4096
+JvmArgs=-XX:+UseG1GC -Dfoo=bar
+";
+
+    #[test]
+    fn parses_general_section() {
+        let config = parse_config(CONFIG);
+        assert_eq!(config.get("name").map(String::as_str), Some("Survival"));
+        assert_eq!(config.get("MaxMemAlloc").map(String::as_str), Some("4096"));
+        assert_eq!(config.get("JvmArgs").map(String::as_str), Some("-XX:+UseG1GC -Dfoo=bar"));
+        assert!(!config.contains_key("[General]"));
+    }
+
+    #[test]
+    fn java_settings_only_keep_overrides() {
+        assert_eq!(java_settings(&parse_config(CONFIG)), JavaSettings::default());
+
+        let overridden = format!("{}OverrideJavaLocation=true\nOverrideMemory=true\nOverrideJavaArgs=true\n", CONFIG);
+        assert_eq!(java_settings(&parse_config(&overridden)), JavaSettings {
+            path: Some(String::from("/usr/lib/jvm/java-17/bin/java")),
+            memory: Some(String::from("4096M")),
+            args: vec![String::from("-XX:+UseG1GC"), String::from("-Dfoo=bar")],
+        });
+
+        let some_overridden = format!("{}OverrideMemory=true\nOverrideJavaArgs=false\n", CONFIG);
+        assert_eq!(java_settings(&parse_config(&some_overridden)), JavaSettings {
+            memory: Some(String::from("4096M")),
+            ..Default::default()
+        });
+    }
+
+    #[test]
+    fn converts_memory_to_megabytes() {
+        assert_eq!(memory_megabytes("4G"), Some(4096));
+        assert_eq!(memory_megabytes("512m"), Some(512));
+        assert_eq!(memory_megabytes("2048k"), Some(2));
+        assert_eq!(memory_megabytes("1024"), Some(1024));
+        assert_eq!(memory_megabytes("lots"), None);
+    }
+
+    #[test]
+    fn loader_spec_falls_back_to_cached_version() {
+        let pack: MmcPack = serde_json::from_str(r#"{
+            "components": [
+                {"uid": "net.minecraft", "version": "1.20.1", "important": true},
+                {"uid": "net.fabricmc.intermediary", "version": "1.20.1"},
+                {"uid": "net.fabricmc.fabric-loader", "cachedVersion": "0.14.21"}
+            ],
+            "formatVersion": 1
+        }"#).unwrap();
+        assert_eq!(pack.minecraft_version().unwrap(), "1.20.1");
+        assert_eq!(pack.loader_spec().unwrap().as_deref(), Some("fabric@0.14.21"));
+    }
+
+    #[test]
+    fn loader_spec_rejects_two_loaders() {
+        let pack: MmcPack = serde_json::from_str(r#"{
+            "components": [
+                {"uid": "net.minecraft", "version": "1.20.1"},
+                {"uid": "net.minecraftforge", "version": "47.1.0"},
+                {"uid": "org.quiltmc.quilt-loader", "version": "0.19.1"}
+            ],
+            "formatVersion": 1
+        }"#).unwrap();
+        assert!(pack.loader_spec().is_err());
+    }
+
+    fn zip_of(names: &[&str]) -> zip::ZipArchive<std::io::Cursor<Vec<u8>>> {
+        let mut zip = zip::ZipWriter::new(std::io::Cursor::new(Vec::new()));
+        for name in names {
+            zip.start_file(*name, zip::write::FileOptions::default()).unwrap();
+        }
+        zip::ZipArchive::new(zip.finish().unwrap()).unwrap()
+    }
+
+    #[test]
+    fn finds_zip_root() {
+        assert_eq!(zip_root(&zip_of(&["mmc-pack.json", "instance.cfg"])).as_deref(), Some(""));
+        assert_eq!(zip_root(&zip_of(&["Survival/instance.cfg", "Survival/mmc-pack.json", "Survival/.minecraft/options.txt"])).as_deref(),
+                   Some("Survival/"));
+        assert_eq!(zip_root(&zip_of(&["a/b/mmc-pack.json"])), None);
+    }
+}
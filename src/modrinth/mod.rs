@@ -0,0 +1,230 @@
+pub mod resolver;
+
+pub use resolver::{InstallPlan, ResolveOptions};
+
+use std::path::{Component, Path, PathBuf};
+use serde::{Serialize, Deserialize};
+use sha2::Digest;
+use super::download::{InstallError, download_and_check};
+use super::instance::{InstanceManifest, InstalledMod};
+use super::store;
+
+const API_URL: &str = "https://api.modrinth.com/v2";
+//Modrinth asks every client to say who it is
+const USER_AGENT: &str = concat!("downloader/", env!("CARGO_PKG_VERSION"));
+
+pub const MODS_DIR: &str = "mods";
+
+pub fn client() -> Result<reqwest::blocking::Client, InstallError> {
+    Ok(reqwest::blocking::Client::builder()
+                                 .user_agent(USER_AGENT)
+                                 .build()?)
+}
+
+pub fn search(client: &reqwest::blocking::Client, query: &str, game_version: Option<&str>, loader: Option<&str>) -> Result<Vec<SearchHit>, InstallError> {
+    //Facets are ANDed lists of ORed filters
+    let mut facets = vec![vec![String::from("project_type:mod")]];
+    if let Some(game_version) = game_version {
+        facets.push(vec![format!("versions:{}", game_version)]);
+    }
+    if let Some(loader) = loader {
+        facets.push(vec![format!("categories:{}", loader)]);
+    }
+    let facets = serde_json::to_string(&facets)?;
+
+    let result: SearchResult = client.get(&format!("{}/search", API_URL))
+                                     .query(&[("query", query), ("facets", &facets)])
+                                     .send()?
+                                     .error_for_status()?
+                                     .json()?;
+    Ok(result.hits)
+}
+
+//Projects can be looked up by id or slug
+pub fn get_project(client: &reqwest::blocking::Client, project: &str) -> Result<Project, InstallError> {
+    let response = client.get(&format!("{}/project/{}", API_URL, project)).send()?;
+    if response.status() == reqwest::StatusCode::NOT_FOUND {
+        return Err(InstallError::Unsupported(format!("there's no modrinth project called {}", project)));
+    }
+    Ok(response.error_for_status()?.json()?)
+}
+
+//Newest first, as modrinth lists them
+pub fn get_versions(client: &reqwest::blocking::Client, project: &str, game_version: &str, loaders: &[&str]) -> Result<Vec<ModVersion>, InstallError> {
+    let game_versions = serde_json::to_string(&[game_version])?;
+    let loaders = serde_json::to_string(loaders)?;
+    let mut versions: Vec<ModVersion> = client.get(&format!("{}/project/{}/version", API_URL, project))
+                                              .query(&[("game_versions", &game_versions), ("loaders", &loaders)])
+                                              .send()?
+                                              .error_for_status()?
+                                              .json()?;
+    versions.sort_by(|a, b| b.date_published.cmp(&a.date_published));
+    Ok(versions)
+}
+
+pub fn get_version(client: &reqwest::blocking::Client, version_id: &str) -> Result<ModVersion, InstallError> {
+    Ok(client.get(&format!("{}/version/{}", API_URL, version_id))
+             .send()?
+             .error_for_status()?
+             .json()?)
+}
+
+//Quilt loads fabric mods too, so those count as compatible
+pub fn compatible_loaders(loader: &str) -> Vec<&str> {
+    match loader {
+        "quilt" => vec!["quilt", "fabric"],
+        loader => vec![loader],
+    }
+}
+
+pub fn newest_compatible(client: &reqwest::blocking::Client, project: &str, manifest: &InstanceManifest) -> Result<ModVersion, InstallError> {
+    let loader = match &manifest.loader {
+        Some(loader) => loader,
+        None => return Err(InstallError::Unsupported(String::from("mods need an installation with a modloader"))),
+    };
+    get_versions(client, project, &manifest.minecraft, &compatible_loaders(&loader.name))?
+        .into_iter()
+        .next()
+        .ok_or_else(|| InstallError::Unsupported(
+                format!("{} has no version for minecraft {} with {}", project, manifest.minecraft, loader.name)))
+}
+
+//Downloads the version's primary file into the instance's mods folder and records it in the
+//manifest. The caller saves the manifest.
+pub fn install_version(client: &reqwest::blocking::Client, project: &Project, version: &ModVersion, directory: &Path, manifest: &mut InstanceManifest) -> Result<(), InstallError> {
+    let file = version.primary_file().ok_or_else(|| {
+        InstallError::Unsupported(format!("{} {} has no files", project.slug, version.version_number))
+    })?;
+
+    let mods_dir = directory.join(MODS_DIR);
+    std::fs::create_dir_all(&mods_dir)?;
+    let location = mod_path(&mods_dir, &file.filename)?;
+
+    let up_to_date = match std::fs::read(&location) {
+        Err(_) => false,
+        Ok(b) => format!("{:x}", sha2::Sha512::digest(&b)) == file.hashes.sha512,
+    };
+    if !up_to_date {
+        let bytes = download_and_check(&file.url, Some(client))?;
+        let sha512 = format!("{:x}", sha2::Sha512::digest(&bytes));
+        if sha512 != file.hashes.sha512 {
+            return Err(InstallError::HashError(
+                    format!("{} has sha512 {}, expected {}", file.url, sha512, file.hashes.sha512)));
+        }
+        store::place(&bytes, &location)?;
+    }
+
+    let installed = InstalledMod {
+        project_id: project.id.clone(),
+        slug: project.slug.clone(),
+        version_id: version.id.clone(),
+        version_number: version.version_number.clone(),
+        file_name: file.filename.clone(),
+        url: file.url.clone(),
+        sha512: file.hashes.sha512.clone(),
+    };
+    //An older version of the mod would get loaded alongside the new one
+    if let Some(old) = manifest.add_mod(installed) {
+        if old.file_name != file.filename {
+            match std::fs::remove_file(mod_path(&mods_dir, &old.file_name)?) {
+                Err(e) if e.kind() != std::io::ErrorKind::NotFound => return Err(e.into()),
+                _ => (),
+            }
+        }
+    }
+    println!("Installed {} {}", project.slug, version.version_number);
+    Ok(())
+}
+
+//File names come from the api, so one that isn't just a name could go anywhere
+pub fn mod_path(mods_dir: &Path, file_name: &str) -> Result<PathBuf, InstallError> {
+    let mut components = Path::new(file_name).components();
+    match (components.next(), components.next()) {
+        (Some(Component::Normal(_)), None) if !file_name.contains(['\\', ':']) => Ok(mods_dir.join(file_name)),
+        _ => Err(InstallError::Unsupported(format!("{} isn't a valid mod file name", file_name))),
+    }
+}
+
+#[derive(Serialize, Deserialize, Debug)]
+pub struct SearchResult {
+    pub hits: Vec<SearchHit>,
+    pub total_hits: u32,
+}
+
+#[derive(Serialize, Deserialize, Debug, Clone)]
+pub struct SearchHit {
+    pub project_id: String,
+    pub slug: String,
+    pub title: String,
+    pub description: String,
+}
+
+#[derive(Serialize, Deserialize, Debug, Clone)]
+pub struct Project {
+    pub id: String,
+    pub slug: String,
+    pub title: String,
+    pub description: String,
+    pub project_type: String,
+}
+
+#[derive(Serialize, Deserialize, Debug, Clone)]
+pub struct ModVersion {
+    pub id: String,
+    pub project_id: String,
+    pub name: String,
+    pub version_number: String,
+    pub version_type: String,
+    pub game_versions: Vec<String>,
+    pub loaders: Vec<String>,
+    pub date_published: String,
+    #[serde(default)]
+    pub dependencies: Vec<Dependency>,
+    pub files: Vec<ModFile>,
+}
+
+impl ModVersion {
+    //Versions without a file marked primary just have the one that matters first
+    pub fn primary_file(&self) -> Option<&ModFile> {
+        self.files.iter()
+                  .find(|f| f.primary)
+                  .or_else(|| self.files.first())
+    }
+}
+
+#[derive(Serialize, Deserialize, Debug, Clone)]
+pub struct Dependency {
+    pub version_id: Option<String>,
+    pub project_id: Option<String>,
+    pub file_name: Option<String>,
+    pub dependency_type: String,
+}
+
+#[derive(Serialize, Deserialize, Debug, Clone)]
+pub struct ModFile {
+    pub hashes: FileHashes,
+    pub url: String,
+    pub filename: String,
+    pub primary: bool,
+    pub size: u64,
+}
+
+#[derive(Serialize, Deserialize, Debug, Clone)]
+pub struct FileHashes {
+    pub sha1: String,
+    pub sha512: String,
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn mod_paths_are_plain_names() {
+        let mods_dir = Path::new("mods");
+        assert_eq!(mod_path(mods_dir, "sodium-0.5.3.jar").unwrap(), mods_dir.join("sodium-0.5.3.jar"));
+        for name in ["../evil.jar", "a/b.jar", "/evil.jar", "..\\evil.jar", "C:evil.jar", ".", ""] {
+            assert!(mod_path(mods_dir, name).is_err(), "{} was let through", name);
+        }
+    }
+}
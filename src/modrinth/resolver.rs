@@ -0,0 +1,208 @@
+//Works out everything a mod pulls in before anything gets downloaded, so a conflict halfway
+//through doesn't leave an instance with half a set of mods.
+use std::collections::{HashMap, VecDeque};
+use std::fmt;
+use super::super::download::InstallError;
+use super::super::instance::InstanceManifest;
+use super::{Project, ModVersion, compatible_loaders, get_project, get_version, newest_compatible};
+
+#[derive(Debug, Clone, Copy, Default)]
+pub struct ResolveOptions {
+    pub include_optional: bool,
+}
+
+#[derive(Debug, Clone, PartialEq)]
+pub enum Reason {
+    Requested,
+    RequiredBy(String),
+    OptionalFor(String),
+}
+
+#[derive(Debug, Clone)]
+pub struct PlannedMod {
+    pub project: Project,
+    pub version: ModVersion,
+    pub reason: Reason,
+    //Some dependency asked for exactly this version
+    pub pinned: bool,
+    //Already in the instance at this version, so there's nothing to download
+    pub installed: bool,
+}
+
+#[derive(Debug, Default)]
+pub struct InstallPlan {
+    pub mods: Vec<PlannedMod>,
+    pub conflicts: Vec<String>,
+    //Dependencies that can't be looked up, i.e. ones only named by a file
+    pub warnings: Vec<String>,
+}
+
+impl InstallPlan {
+    pub fn has_conflicts(&self) -> bool {
+        !self.conflicts.is_empty()
+    }
+
+    pub fn to_install(&self) -> impl Iterator<Item = &PlannedMod> {
+        self.mods.iter().filter(|m| !m.installed)
+    }
+}
+
+impl fmt::Display for InstallPlan {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        for planned in &self.mods {
+            let reason = match &planned.reason {
+                Reason::Requested => String::from("requested"),
+                Reason::RequiredBy(by) => format!("required by {}", by),
+                Reason::OptionalFor(by) => format!("optional for {}", by),
+            };
+            let state = if planned.installed { ", already installed" } else { "" };
+            writeln!(f, "  {} {} ({}{})", planned.project.slug, planned.version.version_number, reason, state)?;
+        }
+        for warning in &self.warnings {
+            writeln!(f, "  warning: {}", warning)?;
+        }
+        for conflict in &self.conflicts {
+            writeln!(f, "  conflict: {}", conflict)?;
+        }
+        Ok(())
+    }
+}
+
+struct Request {
+    project: String,
+    version_id: Option<String>,
+    reason: Reason,
+}
+
+pub fn resolve(client: &reqwest::blocking::Client, project: &str, manifest: &InstanceManifest, options: ResolveOptions) -> Result<InstallPlan, InstallError> {
+    let mut plan = InstallPlan::default();
+    //Project id to position in plan.mods
+    let mut planned: HashMap<String, usize> = HashMap::new();
+    //Project ids that can't be installed, with the slug of whatever said so
+    let mut incompatible: Vec<(String, String)> = Vec::new();
+
+    let mut queue = VecDeque::new();
+    queue.push_back(Request { project: String::from(project), version_id: None, reason: Reason::Requested });
+
+    while let Some(request) = queue.pop_front() {
+        let project = get_project(client, &request.project)?;
+        let requester = match &request.reason {
+            Reason::Requested => None,
+            Reason::RequiredBy(by) | Reason::OptionalFor(by) => Some(by.clone()),
+        };
+
+        if let Some(&i) = planned.get(&project.id) {
+            //Already picked. Only a dependency wanting some other exact version is a problem.
+            let existing = &plan.mods[i];
+            if let Some(version_id) = &request.version_id {
+                if version_id != &existing.version.id {
+                    plan.conflicts.push(format!("{} needs a different version of {} than {}",
+                                                requester.unwrap_or_default(), project.slug, existing.version.version_number));
+                }
+            }
+            continue;
+        }
+
+        let installed = manifest.mods.iter().find(|m| m.project_id == project.id);
+        let version = match choose_version(client, &project, &request, manifest, installed.map(|m| m.version_id.as_str())) {
+            Ok(version) => version,
+            Err(InstallError::Unsupported(reason)) if request.reason != Reason::Requested => {
+                match &request.reason {
+                    Reason::OptionalFor(_) => plan.warnings.push(format!("skipping optional {}: {}", project.slug, reason)),
+                    _ => plan.conflicts.push(format!("{} requires {}, but {}", requester.unwrap_or_default(), project.slug, reason)),
+                }
+                continue;
+            }
+            Err(e) => return Err(e),
+        };
+
+        for dependency in &version.dependencies {
+            let target = match (&dependency.project_id, &dependency.version_id) {
+                (Some(project_id), _) => Some(project_id.clone()),
+                //Some versions only give the version, which says which project it's from
+                (None, Some(version_id)) => Some(get_version(client, version_id)?.project_id),
+                (None, None) => None,
+            };
+            let target = match target {
+                Some(target) => target,
+                None => {
+                    if dependency.dependency_type == "required" {
+                        plan.warnings.push(format!("{} requires {}, which isn't on modrinth",
+                                                   project.slug, dependency.file_name.as_deref().unwrap_or("an unnamed file")));
+                    }
+                    continue;
+                }
+            };
+
+            match &dependency.dependency_type[..] {
+                "required" => queue.push_back(Request {
+                    project: target,
+                    version_id: dependency.version_id.clone(),
+                    reason: Reason::RequiredBy(project.slug.clone()),
+                }),
+                "optional" if options.include_optional => queue.push_back(Request {
+                    project: target,
+                    version_id: dependency.version_id.clone(),
+                    reason: Reason::OptionalFor(project.slug.clone()),
+                }),
+                "incompatible" => incompatible.push((target, project.slug.clone())),
+                //Embedded dependencies ship inside the mod's own jar
+                _ => (),
+            }
+        }
+
+        planned.insert(project.id.clone(), plan.mods.len());
+        plan.mods.push(PlannedMod {
+            installed: installed.map(|m| m.version_id == version.id).unwrap_or(false),
+            pinned: request.version_id.is_some(),
+            project,
+            version,
+            reason: request.reason,
+        });
+    }
+
+    //Incompatibilities count against both the plan and what's already installed
+    for (project_id, by) in incompatible {
+        let clash = plan.mods.iter()
+                             .find(|m| m.project.id == project_id)
+                             .map(|m| m.project.slug.clone())
+                             .or_else(|| manifest.mods.iter()
+                                                 .find(|m| m.project_id == project_id)
+                                                 .map(|m| m.slug.clone()));
+        if let Some(slug) = clash {
+            plan.conflicts.push(format!("{} is incompatible with {}", by, slug));
+        }
+    }
+
+    Ok(plan)
+}
+
+fn choose_version(client: &reqwest::blocking::Client, project: &Project, request: &Request, manifest: &InstanceManifest, installed: Option<&str>) -> Result<ModVersion, InstallError> {
+    if let Some(version_id) = &request.version_id {
+        let version = get_version(client, version_id)?;
+        if !is_compatible(&version, manifest) {
+            return Err(InstallError::Unsupported(
+                    format!("the version it needs, {}, isn't for this instance", version.version_number)));
+        }
+        return Ok(version);
+    }
+
+    //Dependencies that are already there stay put instead of being upgraded along the way
+    if let (Some(version_id), false) = (installed, request.reason == Reason::Requested) {
+        let version = get_version(client, version_id)?;
+        if is_compatible(&version, manifest) {
+            return Ok(version);
+        }
+    }
+
+    newest_compatible(client, &project.id, manifest)
+}
+
+fn is_compatible(version: &ModVersion, manifest: &InstanceManifest) -> bool {
+    let loaders = match &manifest.loader {
+        Some(loader) => compatible_loaders(&loader.name),
+        None => return false,
+    };
+    version.game_versions.contains(&manifest.minecraft)
+        && version.loaders.iter().any(|l| loaders.contains(&l.as_str()))
+}
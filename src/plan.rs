@@ -0,0 +1,302 @@
+use std::collections::BTreeMap;
+use std::fmt;
+use std::path::{Path, PathBuf};
+use rayon::prelude::*;
+use serde::Serialize;
+use sha2::Digest;
+use super::download::{self, AssetIndexObjects, FileKind, InstallError, RESOURCE_URL, download_and_check, human_bytes};
+use super::instance::InstanceLoader;
+use super::mc_data::mojang_version_data::MojangVersionData;
+use super::modloader::Side;
+use super::server::SERVER_JAR;
+use super::store;
+
+//Everything installing a version would download, worked out before anything is
+#[derive(Serialize, Debug, Clone)]
+pub struct DownloadPlan {
+    pub minecraft: String,
+    pub directory: PathBuf,
+    //Loaders run their own installers, so only which one is known ahead of time
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub loader: Option<InstanceLoader>,
+    pub files: Vec<PlannedFile>,
+    //Files without a known size count for nothing
+    pub download_bytes: u64,
+}
+
+//A file an install or an instance needs, and what it should be. Hashes and sizes are only as
+//known as whatever listed the file: fabric's profile has no hashes, so those come from the
+//lockfile if there is one.
+#[derive(Serialize, Debug, Clone)]
+pub struct PlannedFile {
+    pub kind: FileKind,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub url: Option<String>,
+    pub destination: PathBuf,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub sha1: Option<String>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub sha512: Option<String>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub size: Option<u64>,
+    //Already there and right, so it won't be downloaded
+    pub satisfied: bool,
+    //Already fetched while planning, so it's written instead of downloaded again
+    #[serde(skip)]
+    pub contents: Option<Vec<u8>>,
+}
+
+#[derive(Debug, Clone, PartialEq)]
+pub enum Problem {
+    Missing,
+    WrongSize(u64),
+    WrongHash(String),
+}
+
+impl fmt::Display for Problem {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match self {
+            Problem::Missing => write!(f, "missing"),
+            Problem::WrongSize(size) => write!(f, "{} bytes", size),
+            Problem::WrongHash(hash) => write!(f, "hash is {}", hash),
+        }
+    }
+}
+
+impl PlannedFile {
+    pub fn new(kind: FileKind, destination: PathBuf, url: Option<String>, sha1: Option<String>, size: Option<u64>) -> Self {
+        PlannedFile { kind, url, destination, sha1, sha512: None, size, satisfied: false, contents: None }
+    }
+
+    //Reads the file and compares it with what it should be, without downloading anything
+    pub fn check(&self) -> Result<Option<Problem>, InstallError> {
+        let bytes = match std::fs::read(&self.destination) {
+            Ok(bytes) => bytes,
+            Err(e) if e.kind() == std::io::ErrorKind::NotFound => return Ok(Some(Problem::Missing)),
+            Err(e) => return Err(e.into()),
+        };
+        if let Some(size) = self.size {
+            if bytes.len() as u64 != size {
+                return Ok(Some(Problem::WrongSize(bytes.len() as u64)));
+            }
+        }
+        if let Some(sha1) = &self.sha1 {
+            let actual = sha1::Sha1::from(&bytes).digest().to_string();
+            if &actual != sha1 {
+                return Ok(Some(Problem::WrongHash(actual)));
+            }
+        }
+        if let Some(sha512) = &self.sha512 {
+            let actual = format!("{:x}", sha2::Sha512::digest(&bytes));
+            if &actual != sha512 {
+                return Ok(Some(Problem::WrongHash(actual)));
+            }
+        }
+        Ok(None)
+    }
+
+    //Asset objects are named after their hash and only ever written whole, so one that's there
+    //with the right size is taken as good instead of hashing the whole store every install
+    fn is_satisfied(&self) -> bool {
+        match (self.kind, self.size) {
+            (FileKind::AssetObject, Some(size)) => std::fs::metadata(&self.destination).map(|m| m.len() == size).unwrap_or(false),
+            _ => matches!(self.check(), Ok(None)),
+        }
+    }
+
+    //Downloads the file, checking it against every hash that's known
+    pub fn download(&self, client: &reqwest::blocking::Client) -> Result<(), InstallError> {
+        let url = self.url.as_ref().ok_or_else(|| {
+            InstallError::Unsupported(format!("{} has nowhere to download it from, reinstall the instance instead", self.destination.display()))
+        })?;
+        if let (true, Some(sha1), None) = (self.kind.stored() && self.sha512.is_none(), &self.sha1, &self.contents) {
+            store::fetch(url, sha1, &self.destination, Some(client))?;
+            println!("downloaded {}", self.destination.display());
+            return Ok(());
+        }
+
+        let bytes = match &self.contents {
+            Some(contents) => contents.clone(),
+            None => download_and_check(url, Some(client))?,
+        };
+        let problem = match (&self.sha1, &self.sha512) {
+            (Some(sha1), _) if &sha1::Sha1::from(&bytes).digest().to_string() != sha1 => Some("sha1"),
+            (_, Some(sha512)) if &format!("{:x}", sha2::Sha512::digest(&bytes)) != sha512 => Some("sha512"),
+            _ => None,
+        };
+        if let Some(algorithm) = problem {
+            return Err(InstallError::HashError(format!("{} doesn't have the {} it should", url, algorithm)));
+        }
+        if self.kind.stored() {
+            store::place(&bytes, &self.destination)?;
+        } else {
+            if let Some(parent) = self.destination.parent() {
+                std::fs::create_dir_all(parent)?;
+            }
+            store::unlink(&self.destination)?;
+            std::fs::write(&self.destination, bytes)?;
+        }
+        println!("downloaded {}", self.destination.display());
+        Ok(())
+    }
+}
+
+impl DownloadPlan {
+    //Checks which of the files are already there
+    pub fn new(minecraft: &str, directory: &Path, mut files: Vec<PlannedFile>) -> Self {
+        files.par_iter_mut().for_each(|file| file.satisfied = file.is_satisfied());
+        let download_bytes = files.iter()
+                                  .filter(|file| !file.satisfied)
+                                  .filter_map(|file| file.size)
+                                  .sum();
+        DownloadPlan {
+            minecraft: String::from(minecraft),
+            directory: directory.to_path_buf(),
+            loader: None,
+            files,
+            download_bytes,
+        }
+    }
+
+    //Adds files planned separately, i.e. an instance's mods
+    pub fn extend(&mut self, files: Vec<PlannedFile>) {
+        let added = DownloadPlan::new(&self.minecraft, &self.directory, files);
+        self.download_bytes += added.download_bytes;
+        self.files.extend(added.files);
+    }
+
+    pub fn to_download(&self) -> impl Iterator<Item = &PlannedFile> {
+        self.files.iter().filter(|file| !file.satisfied)
+    }
+
+    //Downloads everything that isn't satisfied yet, in parallel
+    pub fn execute(&self, client: &reqwest::blocking::Client) -> Result<(), InstallError> {
+        self.files.par_iter()
+                  .map(|file| match &file.sha1 {
+                      //Files from before the store get moved into it
+                      Some(sha1) if file.satisfied && file.kind.stored() && !store::contains(sha1) => {
+                          store::place(&std::fs::read(&file.destination)?, &file.destination).map(|_| ())
+                      }
+                      _ if file.satisfied => Ok(()),
+                      _ => file.download(client),
+                  })
+                  .collect()
+    }
+}
+
+impl fmt::Display for DownloadPlan {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        writeln!(f, "Installing minecraft {} to {}", self.minecraft, self.directory.display())?;
+        if let Some(loader) = &self.loader {
+            writeln!(f, "then {} {}, whose files aren't counted below", loader.name, loader.version)?;
+        }
+
+        //There are thousands of asset objects, so those are only counted
+        let mut objects = (0, 0, 0);
+        for file in &self.files {
+            if file.kind == FileKind::AssetObject {
+                if file.satisfied {
+                    objects.0 += 1;
+                } else {
+                    objects.1 += 1;
+                    objects.2 += file.size.unwrap_or(0);
+                }
+                continue;
+            }
+            let size = file.size.map(human_bytes).unwrap_or_else(|| String::from("unknown size"));
+            if file.satisfied {
+                writeln!(f, "  have      {} {}", file.kind.name(), file.destination.display())?;
+            } else {
+                writeln!(f, "  download  {} {} ({})", file.kind.name(), file.destination.display(), size)?;
+            }
+        }
+        writeln!(f, "  {} asset objects to download ({}), {} already there", objects.1, human_bytes(objects.2), objects.0)?;
+        writeln!(f, "{} files to download, {} in total", self.to_download().count(), human_bytes(self.download_bytes))
+    }
+}
+
+//What installing the version into the directory would do. For a client the asset index is read
+//from disk if it's there, or fetched and kept in the plan to see which objects it lists.
+pub fn plan_install(version: &MojangVersionData, directory: &Path, side: Side) -> Result<DownloadPlan, InstallError> {
+    let files = plan_files(version, directory, side, true)?;
+    Ok(DownloadPlan::new(&version.id, directory, files))
+}
+
+//Every file the version needs, without looking at what's already there. Without fetch_index, a
+//missing or broken asset index is listed on its own and none of its objects are.
+pub fn plan_files(version: &MojangVersionData, directory: &Path, side: Side, fetch_index: bool) -> Result<Vec<PlannedFile>, InstallError> {
+    let mut files = Vec::new();
+    match side {
+        Side::Client => {
+            let client = &version.downloads.client;
+            files.push(PlannedFile::new(FileKind::GameJar, directory.join("client.jar"),
+                                        Some(client.url.clone()), Some(client.sha1.clone()), Some(client.size as u64)));
+            let logging = &version.logging.client.file;
+            files.push(PlannedFile::new(FileKind::LoggingConfig, directory.join("client.xml"),
+                                        Some(logging.url.clone()), Some(logging.sha1.clone()), Some(logging.size as u64)));
+            files.extend(plan_libraries(version, Path::new("./libraries")));
+            files.extend(plan_assets(version, Path::new("./assets"), fetch_index)?);
+        }
+        Side::Server => {
+            let server = version.downloads.server.as_ref().ok_or_else(|| {
+                InstallError::Unsupported(format!("{} has no dedicated server download", version.id))
+            })?;
+            files.push(PlannedFile::new(FileKind::GameJar, directory.join(SERVER_JAR),
+                                        Some(server.url.clone()), Some(server.sha1.clone()), Some(server.size as u64)));
+        }
+    }
+    Ok(files)
+}
+
+//The version's libraries and natives, in a library store laid out like a maven repo
+pub fn plan_libraries(version: &MojangVersionData, lib_path: &Path) -> Vec<PlannedFile> {
+    let (libs, natives) = download::get_needed_libraries(version);
+    //Natives can share a jar between platforms, which only needs getting once
+    let mut planned = BTreeMap::new();
+    for (kind, artifacts) in [(FileKind::Library, libs), (FileKind::Native, natives)] {
+        for artifact in artifacts {
+            if let Some(path) = &artifact.path {
+                planned.entry(path.clone()).or_insert_with(|| {
+                    PlannedFile::new(kind, lib_path.join(path), Some(artifact.url.clone()), Some(artifact.sha1.clone()), Some(artifact.size as u64))
+                });
+            }
+        }
+    }
+    planned.into_values().collect()
+}
+
+//The asset index and every object it lists
+pub fn plan_assets(version: &MojangVersionData, assets_path: &Path, fetch_index: bool) -> Result<Vec<PlannedFile>, InstallError> {
+    let index = &version.asset_index;
+    let mut index_file = PlannedFile::new(FileKind::AssetIndex,
+                                          assets_path.join("indexes").join(format!("{}.json", version.assets)),
+                                          Some(index.url.clone()),
+                                          Some(index.sha1.clone()),
+                                          Some(index.size as u64));
+    let bytes = match index_file.check()? {
+        None => std::fs::read(&index_file.destination)?,
+        Some(_) if fetch_index => {
+            let bytes = download_and_check(&index.url, None)?;
+            index_file.contents = Some(bytes.clone());
+            bytes
+        }
+        Some(_) => return Ok(vec![index_file]),
+    };
+    let objects: AssetIndexObjects = serde_json::from_slice(&bytes)?;
+
+    //Different names can be the same object
+    let mut planned = BTreeMap::new();
+    for object in objects.objects.values() {
+        let hash = &object.hash;
+        planned.entry(hash.clone()).or_insert_with(|| {
+            PlannedFile::new(FileKind::AssetObject,
+                             assets_path.join("objects").join(&hash[..2]).join(hash),
+                             Some(format!("{}/{}/{}", RESOURCE_URL, &hash[..2], hash)),
+                             Some(hash.clone()),
+                             Some(object.size))
+        });
+    }
+    let mut files = vec![index_file];
+    files.extend(planned.into_values());
+    Ok(files)
+}
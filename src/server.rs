@@ -0,0 +1,132 @@
+use super::mc_data::mojang_version_data::MojangVersionData;
+use super::download::InstallError;
+use super::instance::InstanceManifest;
+use super::modloader::Side;
+use super::plan;
+use std::path::Path;
+
+pub const SERVER_JAR: &str = "server.jar";
+
+//What vanilla generates on first launch, minus the timestamp header
+const DEFAULT_PROPERTIES: &str = "\
+enable-jmx-monitoring=false
+rcon.port=25575
+gamemode=survival
+enable-command-block=false
+enable-query=false
+level-name=world
+motd=A Minecraft Server
+query.port=25565
+pvp=true
+difficulty=easy
+network-compression-threshold=256
+max-tick-time=60000
+max-players=20
+online-mode=true
+enable-status=true
+allow-flight=false
+broadcast-rcon-to-ops=true
+view-distance=10
+server-ip=
+allow-nether=true
+server-port=25565
+enable-rcon=false
+sync-chunk-writes=true
+op-permission-level=4
+prevent-proxy-connections=false
+resource-pack=
+entity-broadcast-range-percentage=100
+rcon.password=
+player-idle-timeout=0
+force-gamemode=false
+rate-limit=0
+hardcore=false
+white-list=false
+broadcast-console-to-ops=true
+spawn-npcs=true
+spawn-animals=true
+snooper-enabled=true
+function-permission-level=2
+level-type=default
+spawn-monsters=true
+enforce-whitelist=false
+resource-pack-sha1=
+spawn-protection=16
+max-world-size=29999984
+";
+
+const JAVA_MEMORY: &str = "-Xmx2G";
+
+pub fn install_server_to_directory(
+    version: &MojangVersionData,
+    directory: &Path,
+    accept_eula: bool,
+) -> Result<(), InstallError> {
+    let plan = plan::plan_install(version, directory, Side::Server)?;
+    std::fs::create_dir_all(directory)?;
+    plan.execute(&reqwest::blocking::Client::new())?;
+
+    //The server refuses to start until the eula is accepted, but accepting it is up to the user.
+    //Without it the server writes eula=false itself on first launch.
+    if accept_eula {
+        std::fs::write(directory.join("eula.txt"), "eula=true\n")?;
+    }
+
+    //Don't clobber an existing configuration
+    let properties_path = directory.join("server.properties");
+    if !properties_path.exists() {
+        std::fs::write(properties_path, DEFAULT_PROPERTIES)?;
+    }
+
+    write_start_scripts(directory, &[String::from("-jar"), String::from(SERVER_JAR)])?;
+
+    let file = serde_json::to_string_pretty(version)?;
+    std::fs::write(directory.join("version_info.json"), file)?;
+
+    Ok(())
+}
+
+//Writes start.sh and start.bat, which run java with the given arguments from the server directory
+pub fn write_start_scripts(directory: &Path, java_args: &[String]) -> Result<(), InstallError> {
+    write_platform_start_scripts(directory, java_args, java_args)
+}
+
+//Same as write_start_scripts, for servers that need different arguments on windows
+pub fn write_platform_start_scripts(directory: &Path, unix_args: &[String], windows_args: &[String]) -> Result<(), InstallError> {
+    let join_args = |args: &[String]| args.iter()
+                                          .map(|arg| quote_arg(arg))
+                                          .collect::<Vec<String>>()
+                                          .join(" ");
+    //Whatever the instance's manifest sets, or 2G of memory from whichever java is on the path
+    let java = InstanceManifest::load(directory).map(|manifest| manifest.java).unwrap_or_default();
+    let executable = quote_arg(java.path.as_deref().unwrap_or("java"));
+    let mut jvm_args = java.jvm_args();
+    if java.memory.is_none() {
+        jvm_args.insert(0, String::from(JAVA_MEMORY));
+    }
+    let jvm_args = join_args(&jvm_args);
+
+    let args = join_args(unix_args);
+    let sh = format!("#!/bin/sh\ncd \"$(dirname \"$0\")\"\nexec {} {} {} nogui \"$@\"\n", executable, jvm_args, args);
+    let sh_path = directory.join("start.sh");
+    std::fs::write(&sh_path, sh)?;
+    #[cfg(unix)]
+    {
+        use std::os::unix::fs::PermissionsExt;
+        std::fs::set_permissions(&sh_path, std::fs::Permissions::from_mode(0o755))?;
+    }
+
+    let args = join_args(windows_args);
+    let bat = format!("@echo off\r\ncd /d \"%~dp0\"\r\n{} {} {} nogui %*\r\npause\r\n", executable, jvm_args, args);
+    std::fs::write(directory.join("start.bat"), bat)?;
+
+    Ok(())
+}
+
+fn quote_arg(arg: &str) -> String {
+    if arg.contains(char::is_whitespace) {
+        format!("\"{}\"", arg)
+    } else {
+        String::from(arg)
+    }
+}
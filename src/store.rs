@@ -0,0 +1,152 @@
+use std::collections::HashMap;
+use std::path::{Path, PathBuf};
+use std::sync::atomic::{AtomicUsize, Ordering};
+use super::download::{InstallError, download_and_check};
+
+//Every library, game jar and mod downloaded, once, named after its sha1. Instances get hardlinks
+//to what's in here, so a jar used by every instance is only on disk once.
+pub const STORE_DIR: &str = "./store";
+
+//Names temporary files uniquely when the same blob is being stored from more than one thread
+static TMP_COUNTER: AtomicUsize = AtomicUsize::new(0);
+
+//Laid out like assets/objects: the first two characters of the hash, then the hash
+pub fn blob_path(sha1: &str) -> PathBuf {
+    Path::new(STORE_DIR).join(&sha1[..2.min(sha1.len())]).join(sha1)
+}
+
+pub fn contains(sha1: &str) -> bool {
+    blob_path(sha1).is_file()
+}
+
+//Stores the bytes if they aren't already, or the stored copy went bad, returning their sha1
+pub fn insert(bytes: &[u8]) -> Result<String, InstallError> {
+    let sha1 = sha1::Sha1::from(bytes).digest().to_string();
+    let path = blob_path(&sha1);
+    if is_intact(&sha1) {
+        return Ok(sha1);
+    }
+
+    //Written next to where it goes and renamed into place, so a blob is never half there
+    let parent = path.parent().unwrap_or_else(|| Path::new(STORE_DIR));
+    std::fs::create_dir_all(parent)?;
+    let tmp = parent.join(format!("{}.{}.{}.tmp", sha1, std::process::id(), TMP_COUNTER.fetch_add(1, Ordering::Relaxed)));
+    std::fs::write(&tmp, bytes)?;
+    std::fs::rename(&tmp, &path)?;
+    Ok(sha1)
+}
+
+//Puts the blob at destination, replacing whatever's there. The old file is removed first rather
+//than written over, since it could be a link to some other blob.
+pub fn link(sha1: &str, destination: &Path) -> Result<(), InstallError> {
+    link_or_copy(&blob_path(sha1), destination)
+}
+
+//Hardlinks from to to, or copies when they're on different drives or the filesystem can't link
+pub fn link_or_copy(from: &Path, to: &Path) -> Result<(), InstallError> {
+    if let Some(parent) = to.parent() {
+        std::fs::create_dir_all(parent)?;
+    }
+    unlink(to)?;
+    if std::fs::hard_link(from, to).is_err() {
+        std::fs::copy(from, to)?;
+    }
+    Ok(())
+}
+
+//Anything written over a link would change the blob for every instance sharing it, so files
+//that might be links get removed before they're replaced
+pub fn unlink(path: &Path) -> Result<(), InstallError> {
+    match std::fs::remove_file(path) {
+        Err(e) if e.kind() != std::io::ErrorKind::NotFound => Err(e.into()),
+        _ => Ok(()),
+    }
+}
+
+//Stores the bytes and links them to destination, in place of writing them there directly
+pub fn place(bytes: &[u8], destination: &Path) -> Result<String, InstallError> {
+    let sha1 = insert(bytes)?;
+    link(&sha1, destination)?;
+    Ok(sha1)
+}
+
+//Whether the stored blob is really what its name says. Instances share blobs through hardlinks,
+//so a file corrupted in an instance corrupts the blob with it.
+pub fn is_intact(sha1: &str) -> bool {
+    std::fs::read(blob_path(sha1)).map(|bytes| sha1::Sha1::from(bytes).digest().to_string() == sha1).unwrap_or(false)
+}
+
+//Links the blob to destination, downloading it into the store first if it isn't there yet or
+//doesn't match its hash any more. Downloads that don't match the hash are refused.
+pub fn fetch(url: &String, sha1: &str, destination: &Path, client: Option<&reqwest::blocking::Client>) -> Result<(), InstallError> {
+    fetch_blob(url, sha1, client)?;
+    link(sha1, destination)
+}
+
+//Just the download half of fetch, for getting files into the store without putting them anywhere
+pub fn fetch_blob(url: &String, sha1: &str, client: Option<&reqwest::blocking::Client>) -> Result<(), InstallError> {
+    if !is_intact(sha1) {
+        unlink(&blob_path(sha1))?;
+        let bytes = download_and_check(url, client)?;
+        let hash = sha1::Sha1::from(&bytes).digest().to_string();
+        if hash != sha1 {
+            return Err(InstallError::HashError(format!("{} has hash {}, expected {}", url, hash, sha1)));
+        }
+        insert(&bytes)?;
+    }
+    Ok(())
+}
+
+//The store's blobs by inode, so files hardlinked out of it are recognised without hashing them
+#[derive(Debug, Default)]
+pub struct Links {
+    blobs: HashMap<(u64, u64), PathBuf>,
+}
+
+impl Links {
+    #[cfg(unix)]
+    pub fn scan() -> Result<Self, InstallError> {
+        use std::os::unix::fs::MetadataExt;
+        let mut blobs = HashMap::new();
+        let prefixes = match std::fs::read_dir(STORE_DIR) {
+            Ok(prefixes) => prefixes,
+            Err(e) if e.kind() == std::io::ErrorKind::NotFound => return Ok(Links { blobs }),
+            Err(e) => return Err(e.into()),
+        };
+        for prefix in prefixes {
+            let prefix = prefix?;
+            if !prefix.file_type()?.is_dir() {
+                continue;
+            }
+            for blob in std::fs::read_dir(prefix.path())? {
+                let blob = blob?;
+                let metadata = blob.metadata()?;
+                blobs.insert((metadata.dev(), metadata.ino()), blob.path());
+            }
+        }
+        Ok(Links { blobs })
+    }
+
+    //Without inodes to go by, files are hashed instead
+    #[cfg(not(unix))]
+    pub fn scan() -> Result<Self, InstallError> {
+        Ok(Links::default())
+    }
+
+    //The blob the file is a hardlink of, if it's one of the store's
+    #[cfg(unix)]
+    pub fn blob_of(&self, path: &Path) -> Result<Option<PathBuf>, InstallError> {
+        use std::os::unix::fs::MetadataExt;
+        let metadata = std::fs::metadata(path)?;
+        if metadata.nlink() < 2 {
+            return Ok(None);
+        }
+        Ok(self.blobs.get(&(metadata.dev(), metadata.ino())).cloned())
+    }
+
+    #[cfg(not(unix))]
+    pub fn blob_of(&self, path: &Path) -> Result<Option<PathBuf>, InstallError> {
+        let sha1 = sha1::Sha1::from(std::fs::read(path)?).digest().to_string();
+        Ok(Some(blob_path(&sha1)).filter(|_| is_intact(&sha1)))
+    }
+}
@@ -0,0 +1,167 @@
+use std::collections::HashMap;
+use std::fmt;
+use std::path::Path;
+use rayon::prelude::*;
+use super::download::{FileKind, InstallError};
+use super::instance::{self, InstanceManifest, VERSION_FILE};
+use super::lockfile::{self, Lockfile};
+use super::mc_data::mojang_version_data::MojangVersionData;
+use super::modloader::{Side, PROFILE_FILE};
+use super::modrinth::{self, MODS_DIR};
+use super::plan::{self, PlannedFile, Problem};
+
+//Everything the instance needs, going by its version_info.json, loader profile and manifest
+pub fn expected_files(directory: &Path, manifest: &InstanceManifest) -> Result<Vec<PlannedFile>, InstallError> {
+    let vanilla: MojangVersionData = serde_json::from_slice(&std::fs::read(directory.join(VERSION_FILE))?)?;
+    let lock = Lockfile::load(directory).ok();
+    let lib_dir = instance::library_dir(directory, manifest.side);
+    let mut files = plan::plan_files(&vanilla, directory, manifest.side, false)?;
+
+    if directory.join(PROFILE_FILE).is_file() {
+        files.extend(loader_files(directory, &lib_dir, lock.as_ref(), manifest.side)?);
+    }
+
+    files.extend(mod_files(directory, manifest)?);
+    Ok(files)
+}
+
+//The mods and pack files the manifest lists
+pub fn mod_files(directory: &Path, manifest: &InstanceManifest) -> Result<Vec<PlannedFile>, InstallError> {
+    let mut files = Vec::new();
+    for installed in &manifest.mods {
+        let mut file = PlannedFile::new(FileKind::Mod, modrinth::mod_path(&directory.join(MODS_DIR), &installed.file_name)?,
+                                        Some(installed.url.clone()), None, None);
+        file.sha512 = Some(installed.sha512.clone());
+        files.push(file);
+    }
+    for pack_file in &manifest.files {
+        let mut file = PlannedFile::new(FileKind::Mod, directory.join(&pack_file.path), pack_file.downloads.first().cloned(),
+                                        Some(pack_file.sha1.clone()).filter(|sha1| !sha1.is_empty()),
+                                        Some(pack_file.size).filter(|size| *size > 0));
+        file.sha512 = Some(pack_file.sha512.clone()).filter(|sha512| !sha512.is_empty());
+        files.push(file);
+    }
+    Ok(files)
+}
+
+//Forge's profile has hashes for its libraries. Fabric and quilt's don't, so the lockfile fills
+//them in where it can.
+fn loader_files(directory: &Path, lib_dir: &Path, lock: Option<&Lockfile>, side: Side) -> Result<Vec<PlannedFile>, InstallError> {
+    let profile: serde_json::Value = serde_json::from_slice(&std::fs::read(directory.join(PROFILE_FILE))?)?;
+    let mut hashes = HashMap::new();
+    for library in profile["libraries"].as_array().into_iter().flatten() {
+        let artifact = &library["downloads"]["artifact"];
+        if let (Some(path), Some(sha1)) = (artifact["path"].as_str(), artifact["sha1"].as_str()) {
+            hashes.insert(String::from(path), (String::from(sha1), artifact["size"].as_u64()));
+        }
+    }
+    if let Some(lock) = lock {
+        for library in &lock.libraries {
+            hashes.entry(library.path.clone()).or_insert_with(|| (library.sha1.clone(), None));
+        }
+    }
+
+    let mut files = Vec::new();
+    for (path, url) in lockfile::profile_libraries(directory)? {
+        let location = lib_dir.join(&path);
+        //Old forge servers only install the libraries marked for them, so only check what's there
+        if side == Side::Server && !location.exists() && url.is_none() {
+            continue;
+        }
+        let (sha1, size) = match hashes.get(&path) {
+            Some((sha1, size)) => (Some(sha1.clone()), *size),
+            None => (None, None),
+        };
+        files.push(PlannedFile::new(FileKind::LoaderLibrary, location, url, sha1, size));
+    }
+    Ok(files)
+}
+
+#[derive(Debug, Default)]
+pub struct VerifyReport {
+    pub checked: usize,
+    pub broken: Vec<(PlannedFile, Problem)>,
+}
+
+impl fmt::Display for VerifyReport {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        if self.broken.is_empty() {
+            return writeln!(f, "All {} files are as they should be.", self.checked);
+        }
+        writeln!(f, "Checked {} files, {} are broken:", self.checked, self.broken.len())?;
+        for (file, problem) in &self.broken {
+            writeln!(f, "  {} {}: {}", file.kind.name(), file.destination.display(), problem)?;
+        }
+        Ok(())
+    }
+}
+
+//Hashes every file the instance needs. Nothing gets downloaded.
+pub fn verify(name: &str) -> Result<VerifyReport, InstallError> {
+    let (directory, manifest) = instance::find(name)?;
+    let files = expected_files(&directory, &manifest)?;
+    let results = files.par_iter()
+                       .map(|file| file.check().map(|problem| problem.map(|problem| (file.clone(), problem))))
+                       .collect::<Result<Vec<Option<(PlannedFile, Problem)>>, InstallError>>()?;
+    Ok(VerifyReport {
+        checked: files.len(),
+        broken: results.into_iter().flatten().collect(),
+    })
+}
+
+#[derive(Debug, Default)]
+pub struct RepairReport {
+    pub repaired: Vec<PlannedFile>,
+    pub failed: Vec<(PlannedFile, String)>,
+}
+
+impl fmt::Display for RepairReport {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        writeln!(f, "Repaired {} files.", self.repaired.len())?;
+        for (file, error) in &self.failed {
+            writeln!(f, "  couldn't repair {} {}: {}", file.kind.name(), file.destination.display(), error)?;
+        }
+        Ok(())
+    }
+}
+
+//Verifies the instance and downloads only what's broken or missing. Objects can only be checked
+//against a good asset index, so when the index was broken they get a second look once it's fixed.
+pub fn repair(name: &str) -> Result<RepairReport, InstallError> {
+    let client = reqwest::blocking::Client::new();
+    let mut report = RepairReport::default();
+    let broken = verify(name)?.broken;
+    let index_broken = broken.iter().any(|(file, _)| file.kind == FileKind::AssetIndex);
+    repair_files(broken, &client, &mut report);
+
+    if index_broken && report.repaired.iter().any(|file| file.kind == FileKind::AssetIndex) {
+        let objects = verify(name)?.broken
+                                   .into_iter()
+                                   .filter(|(file, _)| file.kind == FileKind::AssetObject)
+                                   .collect();
+        repair_files(objects, &client, &mut report);
+    }
+    Ok(report)
+}
+
+fn repair_files(broken: Vec<(PlannedFile, Problem)>, client: &reqwest::blocking::Client, report: &mut RepairReport) {
+    let results: Vec<(PlannedFile, Result<(), InstallError>)> = broken.into_par_iter()
+                                                                      .map(|(file, _)| {
+                                                                          //Only counts as repaired once it checks out
+                                                                          let result = file.download(client).and_then(|_| match file.check()? {
+                                                                              Some(problem) => Err(InstallError::HashError(format!("still broken after downloading it again: {}", problem))),
+                                                                              None => Ok(()),
+                                                                          });
+                                                                          (file, result)
+                                                                      })
+                                                                      .collect();
+    for (file, result) in results {
+        match result {
+            Ok(()) => {
+                println!("repaired {}", file.destination.display());
+                report.repaired.push(file);
+            }
+            Err(e) => report.failed.push((file, e.to_string())),
+        }
+    }
+}